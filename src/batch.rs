@@ -0,0 +1,174 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	process::exit,
+};
+
+use crate::{config::Config, editor::Editor};
+
+/// One line of a batch script
+enum Step {
+	GoTo(usize),
+	Find(String),
+	Replace { from: String, to: String, all: bool },
+	Insert(String),
+	Save,
+}
+
+/// Applies the commands in `script` to each file and exits, with status 1 if any command failed.
+/// Commands stop at the first failure in a file, so a file is only saved if everything before it worked.
+pub fn run(script: &Path, paths: &[PathBuf]) -> ! {
+	let steps = match fs::read_to_string(script) {
+		Ok(text) => parse(&text).unwrap_or_else(|(line, err)| {
+			eprintln!("lili: {}:{line}: {err}", script.display());
+			exit(2);
+		}),
+		Err(err) => {
+			eprintln!("lili: {}: {err}", script.display());
+			exit(2);
+		}
+	};
+	let (config, _) = Config::load();
+	let mut failed = false;
+	for path in paths {
+		let mut editor = match Editor::open_file(path.clone()) {
+			Ok(editor) => editor,
+			Err(err) => {
+				println!("{}: could not open: {err}", path.display());
+				failed = true;
+				continue;
+			}
+		};
+		editor.resolve_settings(&config);
+		match apply(&mut editor, &steps, &config) {
+			Ok(replaced) => {
+				let state = if editor.is_unsaved() {
+					"not saved"
+				} else {
+					"saved"
+				};
+				println!(
+					"{}: {} commands, {replaced} replacements, {state}",
+					path.display(),
+					steps.len()
+				);
+			}
+			Err((line, err)) => {
+				println!(
+					"{}: line {line} of the script failed: {err}",
+					path.display()
+				);
+				failed = true;
+			}
+		}
+	}
+	exit(i32::from(failed));
+}
+
+/// Runs every step, returning the number of replacements made, or the script line that failed
+fn apply(
+	editor: &mut Editor,
+	steps: &[(usize, Step)],
+	config: &Config,
+) -> Result<usize, (usize, String)> {
+	let mut replaced = 0;
+	for (line, step) in steps {
+		let result = match step {
+			Step::GoTo(target) => editor.go_to(*target),
+			Step::Find(text) if editor.find(text) => Ok(()),
+			Step::Find(text) => Err(format!("'{text}' not found")),
+			Step::Replace { from, to, all } => match editor.replace(from, to, *all) {
+				0 => Err(format!("'{from}' not found")),
+				count => {
+					replaced += count;
+					Ok(())
+				}
+			},
+			Step::Insert(text) => {
+				editor.insert_str(text);
+				Ok(())
+			}
+			Step::Save => match editor.path().cloned() {
+				Some(path) => editor
+					.write_to(path, config)
+					.map(|_| ())
+					.map_err(|e| e.to_string()),
+				None => Err("buffer has no file".into()),
+			},
+		};
+		result.map_err(|err| (*line, err))?;
+	}
+	Ok(replaced)
+}
+
+/// Parses the script, skipping empty lines and lines starting with `#`
+fn parse(text: &str) -> Result<Vec<(usize, Step)>, (usize, String)> {
+	let mut steps = Vec::new();
+	for (index, line) in text.lines().enumerate() {
+		let line_num = index + 1;
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		let words = split_words(line).map_err(|err| (line_num, err))?;
+		let step = match words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+			["goto", line] => match line.parse() {
+				Ok(line) => Step::GoTo(line),
+				Err(_) => return Err((line_num, format!("'{line}' is not a line number"))),
+			},
+			["find", text] => Step::Find(text.into()),
+			["replace", from, to] => Step::Replace {
+				from: from.into(),
+				to: to.into(),
+				all: false,
+			},
+			["replace", from, to, "all"] => Step::Replace {
+				from: from.into(),
+				to: to.into(),
+				all: true,
+			},
+			["insert", text] => Step::Insert(text.into()),
+			["save"] => Step::Save,
+			_ => return Err((line_num, format!("invalid command '{line}'"))),
+		};
+		steps.push((line_num, step));
+	}
+	Ok(steps)
+}
+
+/// Splits on whitespace, keeping double quoted strings together.
+/// Quoted strings may contain `\n`, `\t`, `\"` and `\\`.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+	let mut words = Vec::new();
+	let mut chars = line.chars().peekable();
+	while let Some(&ch) = chars.peek() {
+		if ch.is_whitespace() {
+			chars.next();
+		} else if ch == '"' {
+			chars.next();
+			let mut word = String::new();
+			loop {
+				match chars.next() {
+					Some('"') => break,
+					Some('\\') => match chars.next() {
+						Some('n') => word.push('\n'),
+						Some('t') => word.push('\t'),
+						Some(ch @ ('"' | '\\')) => word.push(ch),
+						Some(ch) => return Err(format!("unknown escape '\\{ch}'")),
+						None => return Err("unterminated string".into()),
+					},
+					Some(ch) => word.push(ch),
+					None => return Err("unterminated string".into()),
+				}
+			}
+			words.push(word);
+		} else {
+			let mut word = String::new();
+			while let Some(ch) = chars.next_if(|c| !c.is_whitespace()) {
+				word.push(ch);
+			}
+			words.push(word);
+		}
+	}
+	Ok(words)
+}
@@ -0,0 +1,99 @@
+use std::{env, path::PathBuf, process::exit};
+
+const USAGE: &str = "\
+Usage: lili [OPTIONS] [PATH]...
+
+Opens each file PATH in an editor. If a directory is given, the navigator starts there.
+A PATH of - opens an untitled buffer with the contents of stdin, as does giving no PATH
+when stdin is not a terminal.
+A file PATH may end in :LINE or :LINE:COLUMN to open it with the cursor there.
+Otherwise with no paths, an empty editor is opened.
+
+Options:
+  -p, --print, --cat  Print the files with line numbers instead of opening the editor
+  --batch SCRIPT      Apply the commands in SCRIPT to each file without opening the editor.
+                      Commands, one per line: goto LINE, find TEXT, replace FROM TO [all],
+                      insert TEXT, save. Quote text containing spaces: insert \"a\\n\"
+  -i, --inline        Draw below the shell prompt and leave the output visible afterwards
+  -R, --readonly      Open the files given on the command line read-only
+  --readonly-all      Also open files read-only when they are opened later from the navigator
+  --log FILE          Append debug messages to FILE (LILI_LOG=debug logs to ~/.config/lili/debug.log)
+  -h, --help          Print this help and exit
+  -V, --version       Print the version and exit
+  --                  Treat all following arguments as paths
+";
+
+pub struct Args {
+	pub paths: Vec<PathBuf>,
+	pub read_only: bool,
+	pub read_only_all: bool,
+	/// Print the files to stdout and exit without entering the interactive UI
+	pub print: bool,
+	pub inline: bool,
+	/// File to write debug messages to
+	pub log: Option<PathBuf>,
+	/// Script to apply to the files instead of entering the interactive UI
+	pub batch: Option<PathBuf>,
+}
+
+impl Args {
+	/// Parses the command line, exiting early for `--help`, `--version` and invalid options
+	pub fn parse() -> Self {
+		let mut paths = Vec::new();
+		let mut read_only = false;
+		let mut read_only_all = false;
+		let mut print = false;
+		let mut inline = false;
+		let mut log = None;
+		let mut batch = None;
+		let mut only_paths = false;
+		let mut args = env::args().skip(1);
+		while let Some(arg) = args.next() {
+			if only_paths || !arg.starts_with('-') || arg == "-" {
+				paths.push(PathBuf::from(arg));
+				continue;
+			}
+			match arg.as_str() {
+				"--" => only_paths = true,
+				"-i" | "--inline" => inline = true,
+				"-R" | "--readonly" => read_only = true,
+				"--readonly-all" => read_only_all = true,
+				"-p" | "--print" | "--cat" => print = true,
+				"--log" => match args.next() {
+					Some(path) => log = Some(PathBuf::from(path)),
+					None => fail("--log needs a file path"),
+				},
+				"--batch" => match args.next() {
+					Some(path) => batch = Some(PathBuf::from(path)),
+					None => fail("--batch needs a script file"),
+				},
+				"-h" | "--help" => {
+					print!("{USAGE}");
+					exit(0);
+				}
+				"-V" | "--version" => {
+					println!("lili {}", env!("CARGO_PKG_VERSION"));
+					exit(0);
+				}
+				_ => fail(&format!("unknown option '{arg}'")),
+			}
+		}
+		if batch.is_some() && paths.is_empty() {
+			fail("--batch needs files to edit");
+		}
+		Self {
+			paths,
+			read_only: read_only || read_only_all,
+			read_only_all,
+			print,
+			inline,
+			log,
+			batch,
+		}
+	}
+}
+
+fn fail(message: &str) -> ! {
+	eprintln!("lili: {message}\nTry 'lili --help' for more information.");
+	exit(2);
+}
@@ -0,0 +1,206 @@
+use std::{cell::RefCell, collections::VecDeque, io::Write, rc::Rc};
+
+use crate::config::Config;
+use crate::screen;
+
+/// Clipboard shared by the navigator and all editors; clones are handles to the same contents.
+/// Copied text is also forwarded to the system clipboard or the terminal when possible.
+#[derive(Clone)]
+pub struct Clipboard(Rc<RefCell<Contents>>);
+
+const MAX_ENTRIES: usize = 32;
+const MAX_TOTAL_BYTES: usize = 4 << 20;
+
+struct Contents {
+	/// Copied snippets, newest first
+	history: VecDeque<Entry>,
+	system: SystemClipboard,
+	osc52: bool,
+	osc52_max_bytes: usize,
+}
+
+#[derive(Clone)]
+pub struct Entry {
+	pub text: String,
+	/// Copied as whole lines rather than a selection
+	pub line_wise: bool,
+}
+
+impl Clipboard {
+	pub fn new(config: &Config) -> Self {
+		Self(Rc::new(RefCell::new(Contents {
+			history: VecDeque::new(),
+			system: SystemClipboard::default(),
+			osc52: config.osc52,
+			osc52_max_bytes: config.osc52_max_bytes,
+		})))
+	}
+
+	/// Entry to paste, preferring the system clipboard when it is available.
+	/// Text from the system clipboard is line-wise only if it is what lili copied last.
+	pub fn get(&self) -> Entry {
+		let mut contents = self.0.borrow_mut();
+		let latest = contents.history.front().cloned().unwrap_or(Entry {
+			text: String::new(),
+			line_wise: false,
+		});
+		match contents.system.get() {
+			Some(text) if text != latest.text => Entry {
+				text,
+				line_wise: false,
+			},
+			_ => latest,
+		}
+	}
+
+	pub fn set(&self, text: String, line_wise: bool) {
+		self.0.borrow_mut().push(Entry { text, line_wise });
+	}
+
+	/// Previously copied snippets, newest first
+	pub fn history(&self) -> Vec<Entry> {
+		self.0.borrow().history.iter().cloned().collect()
+	}
+
+	/// Makes an older history entry the current clipboard contents
+	pub fn promote(&self, index: usize) {
+		let mut contents = self.0.borrow_mut();
+		if let Some(entry) = contents.history.remove(index) {
+			contents.push(entry);
+		}
+	}
+
+	/// Message explaining why the system clipboard stopped working, returned only once
+	pub fn take_warning(&self) -> Option<String> {
+		self.0.borrow_mut().system.take_warning()
+	}
+}
+
+impl Contents {
+	fn push(&mut self, entry: Entry) {
+		let in_system_clipboard = self.system.set(&entry.text);
+		if !in_system_clipboard && self.osc52 && entry.text.len() <= self.osc52_max_bytes {
+			osc52_copy(&entry.text);
+		}
+		self.history.retain(|old| old.text != entry.text);
+		self.history.push_front(entry);
+		self.history.truncate(MAX_ENTRIES);
+		let mut total = 0;
+		// the newest entry is always kept, even if it is larger than the limit on its own
+		let keep = self
+			.history
+			.iter()
+			.take_while(|entry| {
+				total += entry.text.len();
+				total <= MAX_TOTAL_BYTES
+			})
+			.count();
+		self.history.truncate(keep.max(1));
+	}
+}
+
+/// The OS clipboard, only available when built with the `system-clipboard` feature.
+/// When it can't be used, callers fall back to the internal clipboard.
+#[derive(Default)]
+struct SystemClipboard {
+	#[cfg(feature = "system-clipboard")]
+	backend: Backend,
+	warning: Option<String>,
+}
+
+#[cfg(feature = "system-clipboard")]
+#[derive(Default)]
+enum Backend {
+	/// Connecting is delayed until the clipboard is first used
+	#[default]
+	Uninit,
+	Ready(arboard::Clipboard),
+	Unavailable,
+}
+
+impl SystemClipboard {
+	/// Returns true if the text was stored in the system clipboard
+	fn set(&mut self, text: &str) -> bool {
+		#[cfg(feature = "system-clipboard")]
+		if let Some(clipboard) = self.backend() {
+			match clipboard.set_text(text) {
+				Ok(()) => return true,
+				Err(err) => self.fail(err),
+			}
+		}
+		_ = text;
+		false
+	}
+
+	/// Text from the system clipboard with line endings normalized, if it is available
+	fn get(&mut self) -> Option<String> {
+		#[cfg(feature = "system-clipboard")]
+		if let Some(clipboard) = self.backend() {
+			match clipboard.get_text() {
+				Ok(text) => return Some(text.replace("\r\n", "\n")),
+				// not an error, the clipboard just holds something else
+				Err(arboard::Error::ContentNotAvailable) => return None,
+				Err(err) => self.fail(err),
+			}
+		}
+		None
+	}
+
+	fn take_warning(&mut self) -> Option<String> {
+		self.warning.take()
+	}
+
+	#[cfg(feature = "system-clipboard")]
+	fn backend(&mut self) -> Option<&mut arboard::Clipboard> {
+		if let Backend::Uninit = self.backend {
+			self.backend = match arboard::Clipboard::new() {
+				Ok(clipboard) => Backend::Ready(clipboard),
+				Err(err) => {
+					self.fail(err);
+					Backend::Unavailable
+				}
+			};
+		}
+		match &mut self.backend {
+			Backend::Ready(clipboard) => Some(clipboard),
+			_ => None,
+		}
+	}
+
+	#[cfg(feature = "system-clipboard")]
+	fn fail(&mut self, err: arboard::Error) {
+		self.backend = Backend::Unavailable;
+		self.warning = Some(format!(
+			"System clipboard unavailable, using internal clipboard ({err})"
+		));
+	}
+}
+
+/// Sends text to the terminal's clipboard with an OSC 52 escape sequence.
+/// Terminals that don't support it ignore the sequence.
+fn osc52_copy(text: &str) {
+	// best effort, a broken terminal shows up on the next redraw anyway
+	_ = write!(screen::out(), "\x1b]52;c;{}\x07", base64(text.as_bytes()));
+	_ = screen::out().flush();
+}
+
+fn base64(data: &[u8]) -> String {
+	const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+	for chunk in data.chunks(3) {
+		let bytes = [
+			chunk[0],
+			*chunk.get(1).unwrap_or(&0),
+			*chunk.get(2).unwrap_or(&0),
+		];
+		let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+		for i in 0..4 {
+			if i <= chunk.len() {
+				out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+			} else {
+				out.push('=');
+			}
+		}
+	}
+	out
+}
@@ -1,21 +1,562 @@
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	env, fs,
+	io::{self, ErrorKind},
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	keymap,
+	theme::{ColorSupport, Theme},
+	util::History,
+};
+
 pub struct Config {
-	clipboard: String,
 	pub line_numbers: bool,
+	/// Start new lines with the indentation of the previous one
+	pub auto_indent: bool,
+	/// Color comments, strings and keywords in languages lili knows about
+	pub syntax_highlighting: bool,
+	/// Lines kept visible above and below the cursor when scrolling
+	pub scroll_margin: usize,
+	/// Draw tabs as `→` and trailing spaces as `·`
+	pub show_whitespace: bool,
+	/// Save files after a few seconds without input and when leaving the editor
+	pub autosave: bool,
+	/// Insert the closing bracket or quote when typing an opening one
+	pub auto_close: bool,
+	/// End saved files with a newline if they don't already
+	pub final_newline: bool,
+	/// Write settings back to the config file whenever they are changed at runtime
+	pub auto_persist: bool,
+	pub theme: Theme,
+	/// One of `Theme::NAMES`, what `theme` was built from
+	pub theme_name: String,
+	/// Colors from the `[theme]` section, applied on top of the named theme
+	theme_colors: Vec<(String, String)>,
+	/// Overrides the detected color support when set
+	pub colors: Option<ColorSupport>,
+	/// Also send copied text to the terminal's clipboard using OSC 52
+	pub osc52: bool,
+	/// Larger copies are kept internal only, since some terminals choke on huge sequences
+	pub osc52_max_bytes: usize,
+	pub tab_width: usize,
+	pub indent_with_spaces: bool,
+	/// Strip spaces and tabs from the ends of lines when saving
+	pub trim_trailing_whitespace: bool,
+	/// Draw below the shell prompt instead of on the alternate screen, like `less -X`
+	pub inline: bool,
+	/// Capture mouse events; turning it off leaves text selection to the terminal
+	pub mouse: bool,
+	/// Characters that count as part of a word in addition to letters and digits
+	pub word_chars: String,
+	/// Overrides from `[filetype.<extension>]` sections, keyed by lowercase extension or file name
+	filetypes: HashMap<String, FileTypeOverrides>,
+	/// Prompt inputs from this session, shared by all editors
+	pub goto_history: History,
+	pub path_history: History,
+	/// The newest entry is what F3 searches for
+	pub search_history: History,
+	pub replace_history: History,
+}
+
+/// Settings that can differ per file type, resolved once for each editor
+#[derive(Debug, Clone)]
+pub struct FileSettings {
+	/// Extension, file name or interpreter the settings were chosen by, shown in the status line
+	pub filetype: Option<String>,
+	pub tab_width: usize,
+	pub indent_with_spaces: bool,
+	/// Line comment prefix such as `"//"`
+	pub comment: Option<String>,
+	pub trim_trailing_whitespace: bool,
+	/// Shell command that reads the buffer on stdin and writes the formatted text to stdout
+	pub format: Option<String>,
+	/// See `Config::word_chars`
+	pub word_chars: Cow<'static, str>,
+}
+
+/// Word characters besides letters and digits when not configured otherwise
+pub const DEFAULT_WORD_CHARS: &str = "_";
+
+impl FileSettings {
+	pub const DEFAULT: Self = Self {
+		filetype: None,
+		tab_width: 4,
+		indent_with_spaces: false,
+		comment: None,
+		trim_trailing_whitespace: false,
+		format: None,
+		word_chars: Cow::Borrowed(DEFAULT_WORD_CHARS),
+	};
+}
+
+impl Default for FileSettings {
+	fn default() -> Self {
+		Self::DEFAULT
+	}
+}
+
+#[derive(Debug, Default)]
+struct FileTypeOverrides {
+	tab_width: Option<usize>,
+	indent_with_spaces: Option<bool>,
+	comment: Option<String>,
+	trim_trailing_whitespace: Option<bool>,
+	format: Option<String>,
+	word_chars: Option<String>,
 }
 
 impl Config {
 	pub fn new() -> Self {
 		Self {
-			clipboard: String::new(),
 			line_numbers: true,
+			auto_indent: true,
+			syntax_highlighting: true,
+			scroll_margin: 3,
+			show_whitespace: false,
+			autosave: false,
+			auto_close: false,
+			final_newline: true,
+			auto_persist: false,
+			theme: Theme::default(),
+			theme_name: Theme::NAMES[0].into(),
+			theme_colors: Vec::new(),
+			colors: None,
+			osc52: true,
+			osc52_max_bytes: 100_000,
+			tab_width: FileSettings::default().tab_width,
+			indent_with_spaces: FileSettings::default().indent_with_spaces,
+			trim_trailing_whitespace: false,
+			inline: false,
+			mouse: false,
+			word_chars: DEFAULT_WORD_CHARS.into(),
+			filetypes: HashMap::new(),
+			goto_history: History::default(),
+			path_history: History::default(),
+			search_history: History::default(),
+			replace_history: History::default(),
+		}
+	}
+
+	/// Reads the config file, falling back to defaults for anything missing.
+	/// Returns a warning describing the first problem found, if any.
+	pub fn load() -> (Self, Option<String>) {
+		let (text, read_error) = match config_path().map(fs::read_to_string) {
+			Some(Ok(text)) => (text, None),
+			Some(Err(err)) if err.kind() != ErrorKind::NotFound => {
+				(String::new(), Some(format!("Could not read config: {err}")))
+			}
+			_ => (String::new(), None),
+		};
+		let (config, key_overrides, warning) = Self::parse(&text);
+		keymap::set_overrides(key_overrides);
+		(config, read_error.or(warning))
+	}
+
+	/// Settings and rebound keys from the text of a config file, see `load`
+	fn parse(text: &str) -> (Self, Vec<keymap::Override>, Option<String>) {
+		let mut config = Self::new();
+		let mut warning = None;
+		let mut section = String::new();
+		let mut key_overrides = Vec::new();
+		for (line_num, line) in text.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+				section = name.trim().to_owned();
+				continue;
+			}
+			let result = match line.split_once('=') {
+				Some((key, value)) if section == "theme" && key.trim() != "name" => {
+					let (key, value) = (key.trim(), value.trim());
+					// colors are applied after the whole file is read so they override the named theme regardless of order
+					Theme::default()
+						.set_color(key, value)
+						.map(|()| config.theme_colors.push((key.into(), value.into())))
+				}
+				Some((key, value)) if section.starts_with("keys.") => {
+					keymap::parse_override(&section[5..], key.trim(), value.trim())
+						.map(|o| key_overrides.push(o))
+				}
+				Some((key, value)) => config.set(&section, key.trim(), value.trim()),
+				None => Err("expected 'key = value'".into()),
+			};
+			if let Err(err) = result {
+				warning.get_or_insert(format!("Config line {}: {err}", line_num + 1));
+			}
+		}
+		config.select_theme(&config.theme_name.clone());
+		(config, key_overrides, warning)
+	}
+
+	/// Switches to one of `Theme::NAMES` with the configured colors applied on top,
+	/// limited to what the terminal can show
+	pub fn select_theme(&mut self, name: &str) {
+		self.theme = Theme::named(name).unwrap_or_default();
+		self.theme_name = name.into();
+		for (key, value) in &self.theme_colors {
+			_ = self.theme.set_color(key, value);
+		}
+		let support = self.colors.unwrap_or_else(ColorSupport::detect);
+		self.theme.limit_colors(support);
+	}
+
+	/// Writes the settings to the config file, keeping comments, unknown keys and other sections intact
+	pub fn save(&self) -> io::Result<()> {
+		let path = config_path()
+			.ok_or_else(|| io::Error::new(ErrorKind::NotFound, "no config directory"))?;
+		let old = match fs::read_to_string(&path) {
+			Ok(text) => text,
+			Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+			Err(err) => return Err(err),
+		};
+		let out = self.merge_settings(&old);
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(path, out)
+	}
+
+	/// The text of the config file `old` with the current settings in place of the old values
+	fn merge_settings(&self, old: &str) -> String {
+		let mut settings: Vec<_> = self.settings().into_iter().map(Some).collect();
+		let mut out = String::new();
+		let mut section = String::new();
+		for line in old.lines() {
+			let trimmed = line.trim();
+			if let Some(name) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+				// settings the section didn't have go at its end
+				write_settings(&mut out, &mut settings, &section);
+				section = name.trim().to_owned();
+			} else if let Some((key, _)) = trimmed.split_once('=') {
+				let setting = settings.iter_mut().find(
+					|s| matches!(s, Some((s, name, _)) if *s == section && *name == key.trim()),
+				);
+				if let Some(setting) = setting {
+					let (_, key, value) = setting.take().unwrap();
+					out += &format!("{key} = {value}\n");
+					continue;
+				}
+			}
+			out += line;
+			out += "\n";
+		}
+		write_settings(&mut out, &mut settings, &section);
+		while let Some((section, _, _)) = settings.iter().flatten().next() {
+			let section = *section;
+			out += &format!("\n[{section}]\n");
+			write_settings(&mut out, &mut settings, section);
+		}
+		out
+	}
+
+	/// Call after a setting was changed at runtime
+	pub fn settings_changed(&self) -> io::Result<()> {
+		if self.auto_persist {
+			self.save()
+		} else {
+			Ok(())
+		}
+	}
+
+	/// Whether a `[filetype.*]` section sets `trim_trailing_whitespace` for `filetype`
+	pub fn overrides_trim(&self, filetype: Option<&str>) -> bool {
+		filetype
+			.and_then(|filetype| self.filetypes.get(filetype))
+			.is_some_and(|overrides| overrides.trim_trailing_whitespace.is_some())
+	}
+
+	/// Settings for a file, taking `[filetype.*]` overrides matching its extension, name
+	/// or, failing those, the interpreter in a `#!` line into account
+	pub fn file_settings(&self, path: Option<&Path>, first_line: &str) -> FileSettings {
+		let extension = path
+			.and_then(Path::extension)
+			.map(|e| e.to_string_lossy().to_lowercase());
+		let name = path
+			.and_then(Path::file_name)
+			.map(|n| n.to_string_lossy().to_lowercase());
+		let shebang = shebang_filetype(first_line);
+		let candidates = [extension.clone(), name, shebang.clone()];
+		let matched = candidates
+			.into_iter()
+			.flatten()
+			.find(|key| self.filetypes.contains_key(key));
+
+		let mut settings = FileSettings {
+			tab_width: self.tab_width,
+			indent_with_spaces: self.indent_with_spaces,
+			trim_trailing_whitespace: self.trim_trailing_whitespace,
+			word_chars: Cow::Owned(self.word_chars.clone()),
+			..FileSettings::DEFAULT
+		};
+		if let Some(overrides) = matched.as_ref().map(|key| &self.filetypes[key]) {
+			if let Some(tab_width) = overrides.tab_width {
+				settings.tab_width = tab_width;
+			}
+			if let Some(indent_with_spaces) = overrides.indent_with_spaces {
+				settings.indent_with_spaces = indent_with_spaces;
+			}
+			if let Some(trim) = overrides.trim_trailing_whitespace {
+				settings.trim_trailing_whitespace = trim;
+			}
+			settings.comment.clone_from(&overrides.comment);
+			settings.format.clone_from(&overrides.format);
+			if let Some(word_chars) = &overrides.word_chars {
+				settings.word_chars = Cow::Owned(word_chars.clone());
+			}
+		}
+		settings.filetype = matched.or(extension).or(shebang);
+		if settings.comment.is_none() {
+			settings.comment = settings
+				.filetype
+				.as_deref()
+				.and_then(default_comment)
+				.map(String::from);
 		}
+		settings
+	}
+
+	fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), String> {
+		if let Some(filetype) = section.strip_prefix("filetype.") {
+			let overrides = self.filetypes.entry(filetype.to_lowercase()).or_default();
+			match key {
+				"tab_width" => overrides.tab_width = Some(parse_tab_width(value)?),
+				"indent_with_spaces" => overrides.indent_with_spaces = Some(parse_bool(value)?),
+				"comment" => overrides.comment = Some(parse_string(value)?),
+				"trim_trailing_whitespace" => {
+					overrides.trim_trailing_whitespace = Some(parse_bool(value)?);
+				}
+				"format" => overrides.format = Some(parse_string(value)?),
+				"word_chars" => overrides.word_chars = Some(parse_string(value)?),
+				_ => (),
+			}
+			return Ok(());
+		}
+		match (section, key) {
+			("", "line_numbers") => self.line_numbers = parse_bool(value)?,
+			("", "auto_indent") => self.auto_indent = parse_bool(value)?,
+			("", "syntax_highlighting") => self.syntax_highlighting = parse_bool(value)?,
+			("", "scroll_margin") => {
+				self.scroll_margin = value
+					.parse()
+					.map_err(|_| format!("expected a number of lines, found '{value}'"))?;
+			}
+			("", "show_whitespace") => self.show_whitespace = parse_bool(value)?,
+			("", "autosave") => self.autosave = parse_bool(value)?,
+			("", "auto_close") => self.auto_close = parse_bool(value)?,
+			("", "final_newline") => self.final_newline = parse_bool(value)?,
+			("", "auto_persist") => self.auto_persist = parse_bool(value)?,
+			("", "osc52") => self.osc52 = parse_bool(value)?,
+			("", "osc52_max_bytes") => {
+				self.osc52_max_bytes = value
+					.parse()
+					.map_err(|_| format!("expected a number of bytes, found '{value}'"))?;
+			}
+			("", "tab_width") => self.tab_width = parse_tab_width(value)?,
+			("", "indent_with_spaces") => self.indent_with_spaces = parse_bool(value)?,
+			("", "trim_trailing_whitespace") => {
+				self.trim_trailing_whitespace = parse_bool(value)?;
+			}
+			("", "inline") => self.inline = parse_bool(value)?,
+			("", "mouse") => self.mouse = parse_bool(value)?,
+			("", "word_chars") => self.word_chars = parse_string(value)?,
+			("", "colors") => self.colors = ColorSupport::parse(&parse_string(value)?)?,
+			("theme", "name") => {
+				let name = parse_string(value)?;
+				if !Theme::NAMES.contains(&name.as_str()) {
+					return Err(format!(
+						"unknown theme '{name}', expected one of {:?}",
+						Theme::NAMES
+					));
+				}
+				self.theme_name = name;
+			}
+			// unknown keys are ignored so that older versions can read newer configs
+			_ => (),
+		}
+		Ok(())
+	}
+
+	/// Global settings as they are written to the config file, with the section each belongs in
+	fn settings(&self) -> Vec<(&'static str, &'static str, String)> {
+		let top = |key, value| ("", key, value);
+		vec![
+			top("line_numbers", self.line_numbers.to_string()),
+			top("auto_indent", self.auto_indent.to_string()),
+			top("syntax_highlighting", self.syntax_highlighting.to_string()),
+			top("scroll_margin", self.scroll_margin.to_string()),
+			top("show_whitespace", self.show_whitespace.to_string()),
+			top("autosave", self.autosave.to_string()),
+			top("auto_close", self.auto_close.to_string()),
+			top("final_newline", self.final_newline.to_string()),
+			top("auto_persist", self.auto_persist.to_string()),
+			top("osc52", self.osc52.to_string()),
+			top("osc52_max_bytes", self.osc52_max_bytes.to_string()),
+			top("tab_width", self.tab_width.to_string()),
+			top("indent_with_spaces", self.indent_with_spaces.to_string()),
+			top(
+				"trim_trailing_whitespace",
+				self.trim_trailing_whitespace.to_string(),
+			),
+			top("inline", self.inline.to_string()),
+			top("mouse", self.mouse.to_string()),
+			top("word_chars", format!("\"{}\"", self.word_chars)),
+			top("colors", format!("\"{}\"", ColorSupport::name(self.colors))),
+			("theme", "name", format!("\"{}\"", self.theme_name)),
+		]
+	}
+}
+
+/// Writes the settings of `section` that haven't been written yet
+fn write_settings(out: &mut String, settings: &mut [Option<(&str, &str, String)>], section: &str) {
+	for setting in settings.iter_mut() {
+		if matches!(setting, Some((s, _, _)) if *s == section) {
+			let (_, key, value) = setting.take().unwrap();
+			*out += &format!("{key} = {value}\n");
+		}
+	}
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+	match value {
+		"true" => Ok(true),
+		"false" => Ok(false),
+		_ => Err(format!("expected true or false, found '{value}'")),
+	}
+}
+
+fn parse_tab_width(value: &str) -> Result<usize, String> {
+	match value.parse() {
+		Ok(width @ 1..=16) => Ok(width),
+		_ => Err(format!(
+			"expected a tab width between 1 and 16, found '{value}'"
+		)),
+	}
+}
+
+fn parse_string(value: &str) -> Result<String, String> {
+	value
+		.strip_prefix('"')
+		.and_then(|v| v.strip_suffix('"'))
+		.map(String::from)
+		.ok_or_else(|| format!("expected a quoted string, found '{value}'"))
+}
+
+/// Line comment prefix of some common languages, used unless `comment` is configured
+fn default_comment(filetype: &str) -> Option<&'static str> {
+	match filetype {
+		"rs" | "c" | "h" | "cpp" | "hpp" | "js" | "ts" | "java" | "go" | "zig" => Some("//"),
+		"py" | "sh" | "toml" | "rb" | "pl" | "yaml" | "yml" | "makefile" | "conf" => Some("#"),
+		"lua" | "sql" => Some("--"),
+		_ => None,
+	}
+}
+
+/// Guesses a file type key from a line like `#!/usr/bin/env python3`
+fn shebang_filetype(first_line: &str) -> Option<String> {
+	let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+	let mut program = words.next()?.rsplit('/').next()?;
+	if program == "env" {
+		program = words.find(|word| !word.starts_with('-'))?;
+	}
+	// python3.11 -> python
+	let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+	let filetype = match program {
+		"python" => "py",
+		"bash" | "sh" | "zsh" | "dash" | "ksh" => "sh",
+		"node" | "nodejs" => "js",
+		"ruby" => "rb",
+		"perl" => "pl",
+		other => other,
+	};
+	Some(filetype.to_owned())
+}
+
+/// Directory where lili keeps its persistent files, usually `~/.config/lili`
+pub fn config_dir() -> Option<PathBuf> {
+	let base = env::var_os("XDG_CONFIG_HOME")
+		.filter(|dir| !dir.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+	Some(base.join("lili"))
+}
+
+fn config_path() -> Option<PathBuf> {
+	config_dir().map(|dir| dir.join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn saved_settings_load_the_same() {
+		let (mut config, _, warning) = Config::parse("line_numbers = false\ntab_width = 2\n");
+		assert!(warning.is_none());
+		config.indent_with_spaces = true;
+		config.word_chars = "_-".into();
+		config.select_theme("light");
+		let (loaded, _, warning) = Config::parse(&config.merge_settings(""));
+		assert!(warning.is_none());
+		assert_eq!(loaded.settings(), config.settings());
+		assert!(!loaded.line_numbers);
+		assert_eq!(loaded.tab_width, 2);
+		assert_eq!(loaded.theme_name, "light");
+	}
+
+	#[test]
+	fn saving_keeps_comments_unknown_keys_and_sections() {
+		let old = "\
+# my settings
+line_numbers = true
+from_a_newer_version = 1
+
+[filetype.rs]
+tab_width = 2
+
+[theme]
+name = \"default\"
+selection_bg = \"#204060\"
+";
+		let (mut config, _, _) = Config::parse(old);
+		config.line_numbers = false;
+		config.select_theme("high-contrast");
+		let saved = config.merge_settings(old);
+		assert!(
+			saved.starts_with("# my settings\nline_numbers = false\nfrom_a_newer_version = 1\n")
+		);
+		assert!(saved.contains("\n[filetype.rs]\ntab_width = 2\n"));
+		assert!(saved.contains("[theme]\nname = \"high-contrast\"\nselection_bg = \"#204060\"\n"));
+		assert_eq!(saved.matches("line_numbers").count(), 1);
+		let (loaded, _, warning) = Config::parse(&saved);
+		assert!(warning.is_none());
+		assert_eq!(
+			loaded.file_settings(Some(Path::new("a.rs")), "").tab_width,
+			2
+		);
 	}
 
-	pub fn clipboard(&self) -> &str {
-		&self.clipboard
+	#[test]
+	fn malformed_lines_fall_back_to_defaults() {
+		let (config, _, warning) =
+			Config::parse("line_numbers = maybe\nnot a setting\ntab_width = 8\n");
+		assert_eq!(
+			warning.as_deref(),
+			Some("Config line 1: expected true or false, found 'maybe'")
+		);
+		assert!(config.line_numbers);
+		assert_eq!(config.tab_width, 8);
 	}
 
-	pub fn set_clipboard(&mut self, text: String) {
-		self.clipboard = text;
+	#[test]
+	fn parsing_returns_rebound_keys() {
+		let (_, key_overrides, warning) =
+			Config::parse("[keys.editor]\n\"ctrl+w\" = \"none\"\n\"ctrl+e\" = \"save\"\n");
+		assert!(warning.is_none());
+		assert_eq!(key_overrides.len(), 2);
 	}
 }
@@ -1,78 +1,308 @@
 use crossterm::{
-	cursor::{self, MoveTo},
-	event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+	cursor,
+	event::{Event, KeyCode, KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
 	queue,
-	terminal::{self, Clear, ClearType},
 };
 use std::{
 	env,
 	fmt::Display,
-	fs::{self, File},
-	io::{self, stdout, Write},
+	fs::{self, File, OpenOptions},
+	io::{self, ErrorKind, Write},
 	ops::Range,
-	path::PathBuf,
+	path::{Path, PathBuf},
+	process::{self, Stdio},
+	sync::atomic::{AtomicUsize, Ordering},
+	thread,
+	time::{Duration, SystemTime},
 };
+use unicode_width::UnicodeWidthChar;
 
-use crate::config::Config;
-use crate::util::{color_highlight, color_reset, read_line};
+use crate::clipboard::Clipboard;
+use crate::config::{Config, FileSettings};
+use crate::highlight::{self, Kind};
+use crate::keymap::{self, Command, Context};
+use crate::log::log;
+use crate::screen;
+use crate::status::{self, StatusBar};
+use crate::text::Text;
+use crate::theme::Style;
+use crate::undo::{Edit, UndoHistory};
+use crate::util::{
+	ask_choice, ask_yes_no, canonical_path, human_size, next_word_end, pick, prev_word_start,
+	read_line, read_path, Position,
+};
+
+/// Time without input before `Config::autosave` saves
+const AUTOSAVE_DELAY: Duration = Duration::from_secs(3);
 
-const TAB_SIZE: usize = 4;
+static NEXT_UNTITLED: AtomicUsize = AtomicUsize::new(1);
 
 #[derive(Debug, Default)]
 pub struct Editor {
-	text: String,
+	text: Text,
 	lines: Vec<Line>,
 	scroll: usize,
 	cursor: Cursor,
 	marker: Option<usize>,
 	path: Option<PathBuf>,
-	active: bool,
+	/// Copied from the config when entered
+	scroll_margin: usize,
+	/// Typed characters replace the one under the cursor
+	overwrite: bool,
+	/// Set by commands that leave the editor, ending `enter`
+	leaving: Option<Leave>,
 	unsaved_changes: bool,
-	message: Option<String>,
+	status: StatusBar,
+	/// Resolved from the config the first time the editor is entered
+	settings: Option<FileSettings>,
+	/// Distinguishes buffers without a path in their titles, never reused
+	untitled_number: usize,
+	history: UndoHistory,
+	read_only: bool,
+	/// Read-only was switched on with `ToggleReadOnly`, so saving anyway is offered
+	read_only_forced_by_user: bool,
+	/// Search match to highlight until the next key press
+	highlight: Option<Range<usize>>,
+	/// Invalid UTF-8 was replaced when opening, so saving would change those bytes
+	lossy: bool,
+	/// Modification time and size of the file when it was last read or written
+	disk_state: Option<DiskState>,
+	/// Incremented on every change to the text
+	version: usize,
+	/// What the text area showed when it was last drawn, None when it must be drawn again
+	last_frame: Option<Frame>,
+}
+
+/// Everything that decides what the text area of an editor looks like
+#[derive(Debug, PartialEq)]
+struct Frame {
+	version: usize,
+	scroll: usize,
+	selection: Option<Range<usize>>,
+	highlight: Option<Range<usize>>,
+	brackets: Vec<usize>,
+	size: (u16, u16),
+	tab_width: usize,
+	line_numbers: bool,
+	syntax_highlighting: bool,
+	show_whitespace: bool,
 }
 
+type DiskState = (SystemTime, u64);
+
 #[derive(Debug, Default)]
 struct Cursor {
 	line: usize,
 	column: usize,
-	// target_column: usize,
+	/// Physical column that vertical movement tries to return to, kept while moving through shorter lines
+	target_column: Option<usize>,
 }
 
 #[derive(Debug)]
-enum Error {
+pub enum Error {
 	WritingToFile(io::Error),
 	CreatingFile(PathBuf, io::Error),
+	/// Flushing the temporary file to disk failed
+	Syncing(io::Error),
+	/// Moving the temporary file over the target failed
+	Renaming(io::Error),
 	CurrentDir,
+	SavingConfig(io::Error),
+	ReadOnly,
+	/// The file changed on disk but could not be read again
+	Reloading(String),
+	/// Drawing or reading input failed
+	Terminal(io::Error),
+}
+
+impl From<io::Error> for Error {
+	fn from(err: io::Error) -> Self {
+		Error::Terminal(err)
+	}
+}
+
+/// Where to go after leaving an editor
+#[derive(Debug, Clone, Copy)]
+pub enum Leave {
+	Navigator,
+	/// Editor this many places further in the list of open editors, wrapping around
+	Step(isize),
+	/// Editor at this index, if there is one
+	To(usize),
+}
+
+/// What happened when writing a file
+pub struct Saved {
+	/// Lines that had trailing whitespace trimmed
+	pub trimmed_lines: usize,
+	/// Whether a newline was appended to the end of the text
+	pub added_newline: bool,
+	/// Why the file had to be written in place instead of replaced by a complete copy
+	pub not_atomic: Option<Error>,
 }
+
+/// Writes to a temporary file next to `path` and renames it over `path`,
+/// so the old contents stay intact if writing fails partway
+fn write_atomically(path: &Path, text: &Text) -> Result<(), Error> {
+	let name = path.file_name().unwrap_or_default().to_string_lossy();
+	let temp = path.with_file_name(format!(".{name}.lili-tmp"));
+	let result = (|| {
+		let mut file = File::create(&temp).map_err(|e| Error::CreatingFile(temp.clone(), e))?;
+		text.write_to(&mut file).map_err(Error::WritingToFile)?;
+		file.sync_all().map_err(Error::Syncing)?;
+		if let Ok(metadata) = fs::metadata(path) {
+			_ = fs::set_permissions(&temp, metadata.permissions());
+		}
+		fs::rename(&temp, path).map_err(Error::Renaming)
+	})();
+	if result.is_err() {
+		_ = fs::remove_file(&temp);
+	}
+	result
+}
+
+/// Whether a failed `write_atomically` may be retried by overwriting the file directly.
+/// Only a directory that doesn't allow the temporary file or the rename qualifies,
+/// other failures would also happen halfway through writing the original.
+fn can_write_in_place(err: &Error) -> bool {
+	match err {
+		Error::Renaming(_) => true,
+		Error::CreatingFile(_, err) => err.kind() == ErrorKind::PermissionDenied,
+		_ => false,
+	}
+}
+
+/// Reasons a file could not be opened in an editor
+#[derive(Debug)]
+pub enum OpenError {
+	NotFound,
+	PermissionDenied,
+	IsDirectory,
+	/// Byte offset of the first invalid sequence
+	InvalidUtf8(usize),
+	/// Size of the file in bytes
+	TooLarge(u64),
+	/// Contains NUL bytes
+	Binary,
+	Other(io::Error),
+}
+
+impl From<io::Error> for OpenError {
+	fn from(err: io::Error) -> Self {
+		match err.kind() {
+			ErrorKind::NotFound => OpenError::NotFound,
+			ErrorKind::PermissionDenied => OpenError::PermissionDenied,
+			ErrorKind::IsADirectory => OpenError::IsDirectory,
+			_ => OpenError::Other(err),
+		}
+	}
+}
+
+/// How far to look for a matching bracket
+const MAX_BRACKET_SCAN: usize = 100_000;
+
+/// Files are read into memory whole, so bigger ones are refused
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
 type EditorResult = Result<(), Error>;
 
 type Line = Range<usize>;
 
 impl Editor {
-	pub fn open_file(path: PathBuf) -> io::Result<Self> {
-		let text = fs::read_to_string(&path)?;
-		Ok(Editor {
-			text,
+	pub fn open_file(path: PathBuf) -> Result<Self, OpenError> {
+		let path = canonical_path(&path);
+		log!("opening {}", path.display());
+		let text =
+			read_text(&path).inspect_err(|err| log!("could not open {}: {err}", path.display()))?;
+		// opening for writing without truncating tells whether this user may write the file
+		let writable = OpenOptions::new().write(true).open(&path).is_ok();
+		let mut editor = Editor {
+			text: text.into(),
+			disk_state: disk_state(&path),
 			path: Some(path),
+			read_only: !writable,
 			..Default::default()
-		})
+		};
+		editor.find_lines();
+		Ok(editor)
+	}
+
+	/// Opens a file that is not valid UTF-8 read-only, with invalid sequences shown as replacement characters
+	pub fn open_lossy(path: PathBuf) -> Result<Self, OpenError> {
+		let path = canonical_path(&path);
+		log!("opening {} lossily", path.display());
+		let bytes = read_bytes(&path)?;
+		let mut editor = Editor {
+			text: String::from_utf8_lossy(&bytes).into_owned().into(),
+			disk_state: disk_state(&path),
+			path: Some(path),
+			read_only: true,
+			lossy: true,
+			..Default::default()
+		};
+		editor.find_lines();
+		Ok(editor)
+	}
+
+	/// Untitled buffer with some initial contents
+	pub fn with_text(text: String) -> Self {
+		let mut editor = Editor {
+			text: text.into(),
+			untitled_number: NEXT_UNTITLED.fetch_add(1, Ordering::Relaxed),
+			..Default::default()
+		};
+		editor.find_lines();
+		editor
 	}
 
 	pub fn new(path: Option<PathBuf>) -> Self {
-		Editor {
-			path,
+		let untitled_number = match path {
+			Some(_) => 0,
+			None => NEXT_UNTITLED.fetch_add(1, Ordering::Relaxed),
+		};
+		let mut editor = Editor {
+			path: path.as_deref().map(canonical_path),
+			untitled_number,
 			..Default::default()
-		}
+		};
+		editor.find_lines();
+		editor
 	}
 
 	pub fn title(&self) -> String {
-		if let Some(path) = &self.path {
-			if let Some(name) = path.file_name() {
-				let decorator = if self.unsaved_changes { "*" } else { " " };
-				return format!("{}{}", decorator, name.to_string_lossy());
-			}
+		let name = self
+			.path
+			.as_ref()
+			.and_then(|path| path.file_name())
+			.map(|name| name.to_string_lossy());
+		self.title_with_name(name.as_deref())
+	}
+
+	/// Like `title`, with `name` shown instead of the file name
+	pub fn title_with_name(&self, name: Option<&str>) -> String {
+		let read_only = if self.read_only { " [RO]" } else { "" };
+		if let Some(name) = name.filter(|_| self.path.is_some()) {
+			let decorator = if self.unsaved_changes { "*" } else { " " };
+			return format!("{decorator}{name}{read_only}");
 		}
-		"*<untitled>".into()
+		format!("*untitled-{}{read_only}", self.untitled_number)
+	}
+
+	/// First non-empty line of a buffer without a path, to tell untitled buffers apart
+	pub fn content_hint(&self) -> Option<String> {
+		if self.path.is_some() {
+			return None;
+		}
+		self.lines
+			.iter()
+			.map(|line| self.text.slice(line.clone()))
+			.find(|line| !line.trim().is_empty())
+			.map(|line| line.trim().to_owned())
+	}
+
+	pub fn set_read_only(&mut self, read_only: bool) {
+		self.read_only = read_only;
+		self.read_only_forced_by_user = false;
 	}
 
 	pub fn is_unsaved(&self) -> bool {
@@ -83,67 +313,354 @@ impl Editor {
 		self.path.as_ref()
 	}
 
-	pub fn enter(&mut self, config: &mut Config) {
-		self.active = true;
-		self.find_lines();
+	/// Points the editor at a file's new location after it was renamed elsewhere
+	pub fn set_path(&mut self, path: PathBuf, config: &Config) {
+		self.disk_state = disk_state(&path);
+		self.path = Some(path);
+		self.resolve_settings(config);
+	}
 
-		while self.active {
-			self.draw(config);
-			self.input(config)
-				.unwrap_or_else(|e| self.message = Some(format!("Error: {e}")));
+	/// Zero-based line the cursor is on
+	pub fn cursor_line(&self) -> usize {
+		self.cursor.line
+	}
+
+	pub fn line_count(&self) -> usize {
+		self.lines.len()
+	}
+
+	/// Runs the editor until the user leaves it, returning where to go next.
+	/// Only fails if the terminal stops working.
+	pub fn enter(&mut self, config: &mut Config, clipboard: &Clipboard) -> io::Result<Leave> {
+		self.leaving = None;
+		self.last_frame = None;
+		self.scroll_margin = config.scroll_margin;
+		self.scroll_to_cursor();
+		if self.settings.is_none() {
+			self.resolve_settings(config);
+		}
+		match self.check_disk() {
+			Err(Error::Terminal(err)) => return Err(err),
+			Err(err) => self.status.error(format!("Error: {err}")),
+			Ok(()) => (),
+		}
+
+		loop {
+			if let Some(leave) = self.leaving.take() {
+				if config.autosave {
+					self.autosave(config);
+				}
+				return Ok(leave);
+			}
+			if let Err(err) = self.draw(config) {
+				if screen::is_transient(&err) {
+					continue;
+				}
+				return Err(err);
+			}
+			if config.autosave && self.is_unsaved() {
+				match screen::poll_event(AUTOSAVE_DELAY) {
+					Ok(true) => (),
+					Ok(false) => {
+						self.autosave(config);
+						continue;
+					}
+					Err(err) if screen::is_transient(&err) => continue,
+					Err(err) => return Err(err),
+				}
+			}
+			match self.input(config, clipboard) {
+				Ok(()) => (),
+				Err(Error::Terminal(err)) if !screen::is_transient(&err) => return Err(err),
+				Err(err) => self.status.error(format!("Error: {err}")),
+			}
 		}
 	}
 
-	fn input(&mut self, config: &mut Config) -> EditorResult {
-		if let Ok(Event::Key(event)) = event::read() {
-			if self.input_movement(&event) {
+	fn input(&mut self, config: &mut Config, clipboard: &Clipboard) -> EditorResult {
+		let event = match screen::read_event()? {
+			Event::Key(event) => event,
+			Event::Mouse(event) => {
+				self.mouse(event, config);
 				return Ok(());
 			}
-			match event.modifiers {
-				KeyModifiers::NONE => match event.code {
-					KeyCode::Esc => self.active = false,
-					KeyCode::Char(ch) => self.insert_char(ch),
-					KeyCode::Enter => self.insert_char('\n'),
-					KeyCode::Tab => self.insert_char('\t'),
-					KeyCode::Backspace => self.backspace(),
-					KeyCode::Delete => self.delete(),
-					_ => (),
-				},
-				KeyModifiers::SHIFT => match event.code {
-					KeyCode::Char(ch) => self.insert_char(ch.to_ascii_uppercase()),
-					_ => (),
-				},
-				KeyModifiers::CONTROL => match event.code {
-					KeyCode::Char('s') => self.save()?,
-					KeyCode::Char('c') => self.copy(config),
-					KeyCode::Char('x') => self.cut(config),
-					KeyCode::Char('v') => self.paste(config),
-					KeyCode::Char('g') => self.go_to_line(),
-					KeyCode::Char('l') => config.line_numbers = !config.line_numbers,
+			Event::Resize(..) => {
+				self.scroll_to_cursor();
+				return Ok(());
+			}
+			_ => return Ok(()),
+		};
+		self.status.dismiss();
+		self.highlight = None;
+		let command = keymap::lookup(Context::Editor, &event);
+		let vertical = [
+			Command::Up,
+			Command::Down,
+			Command::PageUp,
+			Command::PageDown,
+		];
+		if !command.is_some_and(|command| vertical.contains(&command)) {
+			self.cursor.target_column = None;
+		}
+		let Some(command) = command else {
+			if let KeyCode::Char(ch) = event.code {
+				match event.modifiers {
+					KeyModifiers::NONE => self.writable()?.type_char(ch, config.auto_close),
+					KeyModifiers::SHIFT => {
+						let ch = ch.to_ascii_uppercase();
+						self.writable()?.type_char(ch, config.auto_close);
+					}
 					_ => (),
-				},
-				_ => (),
+				}
 			}
+			return Ok(());
+		};
+		if self.input_movement(command, event.modifiers) {
+			return Ok(());
+		}
+		match command {
+			Command::Cancel => self.leaving = Some(Leave::Navigator),
+			Command::ToggleOverwrite => self.overwrite = !self.overwrite,
+			Command::PrevEditor => self.leaving = Some(Leave::Step(-1)),
+			Command::NextEditor => self.leaving = Some(Leave::Step(1)),
+			Command::SwitchEditor => {
+				if let KeyCode::Char(ch) = event.code {
+					self.leaving = Some(Leave::To(ch as usize - '1' as usize));
+				}
+			}
+			Command::Newline => self.writable()?.newline(config.auto_indent),
+			Command::Indent => self.writable()?.indent(),
+			Command::Dedent => self.writable()?.dedent(),
+			Command::CycleIndent => self.cycle_indent(),
+			Command::Backspace => self.writable()?.backspace(config.auto_close),
+			Command::Delete => self.writable()?.delete(),
+			Command::DeleteWordBack => self.writable()?.delete_word(true),
+			Command::DeleteWordForward => self.writable()?.delete_word(false),
+			// files that can't be written stay protected, only a buffer the user locked may be saved anyway
+			Command::Save if !self.read_only_forced_by_user => self.writable()?.save(config)?,
+			Command::Save if ask_yes_no("Buffer is read-only, save anyway?", false)? => {
+				self.save(config)?;
+			}
+			Command::ToggleReadOnly => {
+				self.read_only = !self.read_only;
+				self.read_only_forced_by_user = self.read_only;
+				let state = if self.read_only { "on" } else { "off" };
+				self.status.info(format!("Read-only {state}"));
+			}
+			Command::SaveAs => self.writable()?.save_as(config)?,
+			Command::SelectAll => self.select_all(),
+			Command::Duplicate => self.writable()?.duplicate(),
+			Command::DeleteLine => self.writable()?.delete_lines(),
+			Command::ToggleComment => self.writable()?.toggle_comment(),
+			Command::Copy => self.copy(clipboard),
+			Command::Cut => self.writable()?.cut(clipboard),
+			Command::Paste => self.writable()?.paste(clipboard),
+			Command::PasteHistory => self.writable()?.paste_from_history(config, clipboard)?,
+			Command::GoToLine => self.go_to_line(config)?,
+			Command::Format => self.writable()?.format(),
+			Command::Find => self.find_prompt(config)?,
+			Command::Replace => self.writable()?.replace_prompt(config)?,
+			Command::FindNext => self.find_again(config, false),
+			Command::FindPrevious => self.find_again(config, true),
+			Command::Undo => self.writable()?.undo(),
+			Command::Redo => self.writable()?.redo(),
+			Command::Statistics => {
+				let ((lines, words, chars), bytes, scope) = match self.selection() {
+					Some(range) => {
+						let counts = count_text(self.text.slice(range.clone()).chars());
+						(counts, range.len(), "Selection")
+					}
+					None => (count_text(self.text.chars()), self.text.len(), "File"),
+				};
+				let message =
+					format!("{scope}: {lines} lines, {words} words, {chars} chars, {bytes} bytes");
+				self.status.info(message);
+			}
+			Command::ToggleWhitespace => {
+				config.show_whitespace = !config.show_whitespace;
+				config.settings_changed().map_err(Error::SavingConfig)?;
+			}
+			Command::ToggleAutoClose => {
+				config.auto_close = !config.auto_close;
+				let state = if config.auto_close { "on" } else { "off" };
+				self.status.info(format!("Auto-closing brackets {state}"));
+				config.settings_changed().map_err(Error::SavingConfig)?;
+			}
+			Command::ToggleLineNumbers => {
+				config.line_numbers = !config.line_numbers;
+				config.settings_changed().map_err(Error::SavingConfig)?;
+			}
+			Command::ToggleTrimWhitespace => {
+				config.trim_trailing_whitespace = !config.trim_trailing_whitespace;
+				self.follow_trim_setting(config);
+				let state = if config.trim_trailing_whitespace {
+					"on"
+				} else {
+					"off"
+				};
+				if self.file_settings().trim_trailing_whitespace == config.trim_trailing_whitespace
+				{
+					self.status
+						.info(format!("Trimming trailing whitespace on save {state}"));
+				} else {
+					self.status.info(format!(
+						"Trimming trailing whitespace on save {state}, except for this file type"
+					));
+				}
+				config.settings_changed().map_err(Error::SavingConfig)?;
+			}
+			Command::ToggleAutoIndent => {
+				config.auto_indent = !config.auto_indent;
+				let state = if config.auto_indent { "on" } else { "off" };
+				self.status.info(format!("Auto-indent {state}"));
+				config.settings_changed().map_err(Error::SavingConfig)?;
+			}
+			Command::Help => keymap::show_help(&config.theme)?,
+			Command::ToggleMouse => self.status.info(screen::toggle_mouse_capture()),
+			_ => (),
 		}
 		Ok(())
 	}
 
+	/// Position of the bracket at or just before the cursor, and of its match if there is one nearby
+	fn bracket_match(&self) -> Option<(usize, Option<usize>)> {
+		let pos = self.char_index();
+		let at = [Some(pos), pos.checked_sub(1)]
+			.into_iter()
+			.flatten()
+			.find(|&i| self.text.byte(i).is_some_and(|b| b"()[]{}".contains(&b)))?;
+		Some((at, matching_bracket(&self.text, at)))
+	}
+
+	/// Clicking places the cursor, dragging selects and the wheel scrolls
+	fn mouse(&mut self, event: MouseEvent, config: &Config) {
+		const SCROLL_LINES: usize = 3;
+		let row = screen::relative_row(event.row) as usize;
+		if row >= status::row() as usize {
+			return;
+		}
+		let gutter = if config.line_numbers {
+			self.lines.len().to_string().len() + 1
+		} else {
+			0
+		};
+		let line = (self.scroll + row).min(self.lines.len() - 1);
+		let column = (event.column as usize).saturating_sub(gutter);
+		match event.kind {
+			MouseEventKind::Down(MouseButton::Left) => {
+				self.marker = None;
+				self.click(line, column);
+			}
+			MouseEventKind::Drag(MouseButton::Left) => {
+				if self.marker.is_none() {
+					self.marker = Some(self.char_index());
+				}
+				self.click(line, column);
+			}
+			MouseEventKind::ScrollUp => {
+				self.scroll = self.scroll.saturating_sub(SCROLL_LINES);
+				self.keep_cursor_in_view();
+			}
+			MouseEventKind::ScrollDown => {
+				self.scroll = (self.scroll + SCROLL_LINES).min(self.lines.len() - 1);
+				self.keep_cursor_in_view();
+			}
+			_ => (),
+		}
+	}
+
+	fn click(&mut self, line: usize, physical_column: usize) {
+		self.cursor.target_column = Some(physical_column);
+		self.move_to_line(line);
+		self.cursor.target_column = None;
+	}
+
+	/// Moves the cursor onto the screen after scrolling the view without it
+	fn keep_cursor_in_view(&mut self) {
+		let height = (screen::size().1 as usize).saturating_sub(2);
+		let margin = self.scroll_margin.min(height / 2);
+		let top = if self.scroll == 0 {
+			0
+		} else {
+			self.scroll + margin
+		};
+		let line = self
+			.cursor
+			.line
+			.clamp(top, self.scroll + height - margin)
+			.min(self.lines.len() - 1);
+		if line != self.cursor.line {
+			let scroll = self.scroll;
+			self.move_to_line(line);
+			self.scroll = scroll;
+		}
+	}
+
+	/// Offers to reload the file if another program changed it, or warns if that would lose unsaved changes
+	fn check_disk(&mut self) -> EditorResult {
+		let Some(path) = self.path.clone() else {
+			return Ok(());
+		};
+		if self.disk_state.is_none() {
+			return Ok(());
+		}
+		let current = disk_state(&path);
+		if current == self.disk_state {
+			return Ok(());
+		}
+		if current.is_none() {
+			self.status.error("File was deleted on disk");
+		} else if self.unsaved_changes {
+			self.status
+				.error("File changed on disk, saving will overwrite those changes");
+		} else if ask_yes_no("File changed on disk, reload it?", true)? {
+			let text = read_text(&path).map_err(|err| Error::Reloading(err.to_string()))?;
+			self.replace_text(text);
+			self.unsaved_changes = false;
+			self.history.mark_saved();
+			self.disk_state = current;
+			self.status.info("Reloaded file");
+		}
+		Ok(())
+	}
+
+	/// Gives access to the editor for modifying actions, or fails if the buffer is read-only
+	fn writable(&mut self) -> Result<&mut Self, Error> {
+		if self.read_only {
+			Err(Error::ReadOnly)
+		} else {
+			Ok(self)
+		}
+	}
+
 	/// Cursor movement logic, returns true if cursor moved (so consider the event consumed in that case)
-	fn input_movement(&mut self, event: &KeyEvent) -> bool {
+	fn input_movement(&mut self, command: Command, modifiers: KeyModifiers) -> bool {
 		let prev_pos = self.char_index();
-		let height = terminal::size().unwrap().1 as usize;
-		match event.code {
-			KeyCode::Left => self.move_left(),
-			KeyCode::Right => self.move_right(),
-			KeyCode::Up => self.move_up(1),
-			KeyCode::Down => self.move_down(1),
-			KeyCode::PageUp => self.move_up(height),
-			KeyCode::PageDown => self.move_down(height),
-			KeyCode::Home => self.move_home(),
-			KeyCode::End => self.move_end(),
+		let height = screen::size().1 as usize;
+		match command {
+			Command::Left => self.move_left(),
+			Command::Right => self.move_right(),
+			Command::Up => self.move_up(1),
+			Command::Down => self.move_down(1),
+			Command::PageUp => self.move_up(height),
+			Command::PageDown => self.move_down(height),
+			Command::Home => self.move_home(),
+			Command::End => self.move_end(),
+			Command::WordLeft => self.move_to_byte(self.prev_word_start(prev_pos)),
+			Command::WordRight => self.move_to_byte(self.next_word_end(prev_pos)),
+			Command::MatchingBracket => match self.bracket_match() {
+				Some((at, Some(other))) => {
+					// selecting forwards includes the closing bracket
+					let past = other > at && modifiers.contains(KeyModifiers::SHIFT);
+					self.move_to_byte(other + past as usize);
+				}
+				Some((_, None)) => self.status.info("No matching bracket found"),
+				None => self.status.info("No bracket at the cursor"),
+			},
 			_ => return false,
 		}
-		if event.modifiers.contains(KeyModifiers::SHIFT) {
+		self.scroll_to_cursor();
+		if modifiers.contains(KeyModifiers::SHIFT) {
 			if self.marker.is_none() {
 				self.marker = Some(prev_pos);
 			}
@@ -153,84 +670,181 @@ impl Editor {
 		true
 	}
 
-	fn draw(&mut self, config: &Config) {
-		queue!(stdout(), Clear(ClearType::All)).unwrap();
+	fn draw(&mut self, config: &Config) -> io::Result<()> {
+		let mut out = screen::out();
+		// the terminal may have shrunk, or an edit moved the cursor without scrolling
+		self.scroll_to_cursor();
+		if screen::take_overlay() {
+			self.last_frame = None;
+		}
 
-		let max_rows = terminal::size().unwrap().1 as usize - 1;
+		let max_rows = screen::size().1 as usize - 1;
 		let end = (self.scroll + max_rows).min(self.lines.len());
 		let visible_rows = self.scroll..end;
 
-		let selection = self.selection().unwrap_or_default();
-
 		let line_number_width = self.lines.len().to_string().len();
 
-		for (line_index, line) in self.lines[visible_rows].iter().enumerate() {
-			let text = &self.text[line.clone()];
-
-			queue!(stdout(), MoveTo(0, line_index as u16)).unwrap();
-
-			if config.line_numbers {
-				let line_num = line_index + self.scroll + 1;
-				print!("{line_num:line_number_width$} ");
-			}
-
-			let mut in_selection = false;
-			for (i, char) in text.char_indices() {
-				let char_i = line.start + i;
-				if selection.contains(&char_i) {
-					if !in_selection {
-						color_highlight();
-						in_selection = true;
-					}
-				} else if in_selection {
-					color_reset();
-					in_selection = false;
-				}
-				if char == '\t' {
-					print!("{:1$}", " ", TAB_SIZE);
-				} else {
-					print!("{char}");
-				}
+		let brackets = match self.bracket_match() {
+			Some((at, Some(other))) => vec![at, other],
+			_ => Vec::new(),
+		};
+		let frame = Frame {
+			version: self.version,
+			scroll: self.scroll,
+			selection: self.selection(),
+			highlight: self.highlight.clone(),
+			brackets,
+			size: screen::size(),
+			tab_width: self.tab_width(),
+			line_numbers: config.line_numbers,
+			syntax_highlighting: config.syntax_highlighting,
+			show_whitespace: config.show_whitespace,
+		};
+		if self.last_frame.as_ref() != Some(&frame) {
+			let selection = frame.selection.clone().unwrap_or_default();
+			for (row, line_index) in visible_rows.enumerate() {
+				queue!(out, screen::move_to(0, row as u16))?;
+				self.draw_line(&mut out, line_index, &selection, &frame.brackets, config)?;
+				screen::clear_line_end()?;
 			}
-			color_reset();
+			screen::clear_below((end - self.scroll) as u16)?;
+			self.last_frame = Some(frame);
 		}
-		self.status_line();
+		self.status_line(&mut out, config)?;
 		let cursor_offset = if config.line_numbers {
 			line_number_width + 1
 		} else {
 			0
 		};
 		queue!(
-			stdout(),
-			MoveTo(
+			out,
+			screen::move_to(
 				(self.physical_column() + cursor_offset) as u16,
 				(self.cursor.line - self.scroll) as u16
 			),
 			cursor::Show,
 			cursor::SetCursorStyle::BlinkingBar
-		)
-		.unwrap();
-		stdout().flush().unwrap();
+		)?;
+		out.flush()
 	}
 
-	fn status_line(&mut self) {
-		queue!(stdout(), MoveTo(0, terminal::size().unwrap().1)).unwrap();
+	/// Writes a line with its line number and tabs expanded, starting at the current terminal position
+	fn draw_line(
+		&self,
+		out: &mut impl Write,
+		line_index: usize,
+		selection: &Range<usize>,
+		brackets: &[usize],
+		config: &Config,
+	) -> io::Result<()> {
+		let line = &self.lines[line_index];
+		if config.line_numbers {
+			let line_number_width = self.lines.len().to_string().len();
+			config.theme.line_number.queue(out)?;
+			write!(out, "{:line_number_width$} ", line_index + 1)?;
+			Style::reset(out)?;
+		}
 
-		if let Some(message) = &self.message {
-			print!("{message}");
-			self.message = None;
-		} else {
-			print!(
-				"[{}, {}] {}",
-				self.cursor.line + 1,
-				self.physical_column(),
-				self.title(),
-			);
+		let highlight = self.highlight.clone().unwrap_or_default();
+		let syntax = self
+			.file_settings()
+			.filetype
+			.as_deref()
+			.filter(|_| config.syntax_highlighting)
+			.and_then(highlight::language)
+			.map(|language| highlight::line(language, &self.text.slice(line.clone())))
+			.unwrap_or_default();
+		let mut spans = syntax.iter().peekable();
+		let mut current_style = None;
+		let text = self.text.slice(line.clone());
+		let trailing_start = text.trim_end_matches([' ', '\t']).len();
+		for (i, char) in text.char_indices() {
+			let char_i = line.start + i;
+			while spans.next_if(|(range, _)| range.end <= i).is_some() {}
+			let kind = spans
+				.peek()
+				.filter(|(range, _)| range.contains(&i))
+				.map(|(_, kind)| kind);
+			let shown_whitespace =
+				config.show_whitespace && (char == '\t' || char == ' ' && i >= trailing_start);
+			let style = if selection.contains(&char_i) {
+				Some(config.theme.selection)
+			} else if highlight.contains(&char_i) {
+				Some(config.theme.search)
+			} else if brackets.contains(&char_i) {
+				Some(config.theme.bracket)
+			} else if shown_whitespace {
+				Some(config.theme.dimmed)
+			} else {
+				kind.map(|kind| match kind {
+					Kind::Comment => config.theme.comment,
+					Kind::String => config.theme.string,
+					Kind::Keyword => config.theme.keyword,
+				})
+			};
+			if style != current_style {
+				match style {
+					Some(style) => style.queue(out)?,
+					None => Style::reset(out)?,
+				}
+				current_style = style;
+			}
+			if char == '\t' && shown_whitespace {
+				write!(out, "→{:1$}", "", self.tab_width() - 1)?;
+			} else if char == '\t' {
+				write!(out, "{:1$}", " ", self.tab_width())?;
+			} else if shown_whitespace {
+				write!(out, "·")?;
+			} else {
+				write!(out, "{char}")?;
+			}
 		}
+		Style::reset(out)
 	}
 
-	fn set_message(&mut self, text: String) {
-		self.message = Some(text);
+	/// Writes the whole buffer using the same rendering as the editor view
+	pub fn print(&mut self, out: &mut impl Write, config: &Config) -> io::Result<()> {
+		self.resolve_settings(config);
+		for line_index in 0..self.lines.len() {
+			self.draw_line(out, line_index, &(0..0), &[], config)?;
+			writeln!(out)?;
+		}
+		Ok(())
+	}
+
+	fn status_line(&mut self, out: &mut impl Write, config: &Config) -> io::Result<()> {
+		let filetype = self
+			.file_settings()
+			.filetype
+			.as_ref()
+			.map(|filetype| format!(" ({filetype})"))
+			.unwrap_or_default();
+		let line_count = self.lines.len();
+		let mut position = format!(
+			"[{}/{line_count}, {}] {}%",
+			self.cursor.line + 1,
+			self.physical_column(),
+			(self.cursor.line + 1) * 100 / line_count
+		);
+		if let Some(range) = self.selection() {
+			let (first, last) = self.selected_lines();
+			let text = self.text.slice(range);
+			let words = text.split_whitespace().count();
+			let chars = text.chars().count();
+			let lines = match last - first + 1 {
+				1 => "1 line".into(),
+				n => format!("{n} lines"),
+			};
+			position += &format!("  {words} words, {chars} chars, {lines} selected");
+		}
+		let title = format!(
+			"{}{filetype}  {}  {}  {}",
+			self.title(),
+			human_size(self.text.len() as u64),
+			self.indent_label(),
+			if self.overwrite { "OVR" } else { "INS" }
+		);
+		self.status.draw(out, &config.theme, &position, &title)
 	}
 
 	fn move_left(&mut self) {
@@ -254,32 +868,43 @@ impl Editor {
 	}
 
 	fn move_up(&mut self, lines: usize) {
-		let physical_column = self.text
-			[self.current_line().start..(self.current_line().start + self.cursor.column)]
-			.chars()
-			.count();
-		self.cursor.line = self.cursor.line.saturating_sub(lines);
-		self.cursor.column = physical_column.min(self.current_line().len());
-		self.ensure_char_boundary();
-		self.scroll_to_cursor();
+		self.move_to_line(self.cursor.line.saturating_sub(lines));
 	}
 
 	fn move_down(&mut self, lines: usize) {
-		let physical_column = self.text
-			[self.current_line().start..(self.current_line().start + self.cursor.column)]
-			.chars()
-			.count();
-		self.cursor.line = (self.cursor.line + lines).min(self.lines.len() - 1);
-		self.cursor.column = physical_column.min(self.current_line().len());
-		self.ensure_char_boundary();
+		self.move_to_line((self.cursor.line + lines).min(self.lines.len() - 1));
+	}
+
+	/// Vertical movement, keeping the cursor at the same rendered column where the line is long enough
+	fn move_to_line(&mut self, line: usize) {
+		let target = *self
+			.cursor
+			.target_column
+			.get_or_insert(self.physical_column());
+		self.cursor.line = line;
+		let mut physical = 0;
+		self.cursor.column = self.current_line().len();
+		for (i, ch) in self.text.slice(self.current_line().clone()).char_indices() {
+			physical += self.char_width(ch);
+			if physical > target {
+				self.cursor.column = i;
+				break;
+			}
+		}
 		self.scroll_to_cursor();
 	}
 
+	/// Scrolls just enough to keep the cursor `scroll_margin` lines from the top and bottom,
+	/// or centered if the margin is more than half the screen. Call after any jump of the cursor.
 	fn scroll_to_cursor(&mut self) {
-		let height = terminal::size().unwrap().1 as usize - 2;
-		self.scroll = self
-			.scroll
-			.clamp(self.cursor.line.saturating_sub(height), self.cursor.line);
+		let height = (screen::size().1 as usize).saturating_sub(2);
+		let margin = self.scroll_margin.min(height / 2);
+		// no empty rows are added past the end to keep the margin there
+		let margin_below = margin.min(self.lines.len().saturating_sub(self.cursor.line + 1));
+		self.scroll = self.scroll.clamp(
+			(self.cursor.line + margin_below).saturating_sub(height),
+			self.cursor.line.saturating_sub(margin),
+		);
 	}
 
 	fn move_home(&mut self) {
@@ -292,12 +917,9 @@ impl Editor {
 	}
 
 	fn move_to_byte(&mut self, pos: usize) {
-		for (line_index, line) in self.lines.iter().enumerate() {
-			if (line.start..=line.end).contains(&pos) {
-				self.cursor.line = line_index;
-				self.cursor.column = pos - line.start;
-			}
-		}
+		self.cursor.line = self.line_at(pos);
+		self.cursor.column = pos - self.current_line().start;
+		self.scroll_to_cursor();
 	}
 
 	/// Moves cursor left until it is on a character (in case it was in the middle of a multi-byte character)
@@ -316,85 +938,562 @@ impl Editor {
 
 	fn find_lines(&mut self) {
 		self.lines.clear();
-		let mut this_line = 0..0;
-		for (index, char) in self.text.char_indices() {
-			if char == '\n' {
-				this_line.end = index;
-				self.lines.push(this_line.clone());
-				this_line.start = index + 1;
+		self.lines.extend(self.text.lines());
+	}
+
+	/// Index of the line containing byte `pos`
+	fn line_at(&self, pos: usize) -> usize {
+		self.lines.partition_point(|line| line.end < pos)
+	}
+
+	/// First and last line touched by the selection, or the cursor's line
+	fn selected_lines(&self) -> (usize, usize) {
+		match self.selection() {
+			// a selection ending at the start of a line does not include that line
+			Some(range) => (
+				self.line_at(range.start),
+				self.line_at(range.end.saturating_sub(1).max(range.start)),
+			),
+			None => (self.cursor.line, self.cursor.line),
+		}
+	}
+
+	/// Fixes up `lines` after the bytes in `range` were replaced by `inserted` new ones,
+	/// rescanning only the lines the edit touched instead of the whole text
+	fn update_lines(&mut self, range: Range<usize>, inserted: usize) {
+		let first = self.line_at(range.start);
+		let last = self.line_at(range.end);
+		let start = self.lines[first].start;
+		let end = self.lines[last].end + inserted - range.len();
+		let mut new_lines = Vec::new();
+		let mut line_start = start;
+		for (index, _) in self.text.slice(start..end).match_indices('\n') {
+			new_lines.push(line_start..start + index);
+			line_start = start + index + 1;
+		}
+		new_lines.push(line_start..end);
+		let added = new_lines.len();
+		self.version += 1;
+		self.lines.splice(first..=last, new_lines);
+		for line in &mut self.lines[first + added..] {
+			line.start = line.start + inserted - range.len();
+			line.end = line.end + inserted - range.len();
+		}
+	}
+
+	/// Replaces a byte range of the text, recording the change for undo.
+	/// All changes to the text go through here; the caller decides where the cursor goes.
+	fn edit(&mut self, range: Range<usize>, text: &str) {
+		let edit = Edit {
+			pos: range.start,
+			removed: self.text.slice(range.clone()).into_owned(),
+			inserted: text.to_owned(),
+			cursor: self.char_index(),
+		};
+		self.shift_marker(range.start, range.len(), text.len());
+		self.text.replace_range(range.clone(), text);
+		self.update_lines(range, text.len());
+		self.history.record(edit);
+		self.unsaved_changes = true;
+	}
+
+	fn undo(&mut self) {
+		let Some(step) = self.history.undo().cloned() else {
+			self.status.info("Nothing to undo");
+			return;
+		};
+		for edit in step.iter().rev() {
+			self.revert(edit.pos..edit.pos + edit.inserted.len(), &edit.removed);
+		}
+		self.move_to_byte(step[0].cursor);
+	}
+
+	fn redo(&mut self) {
+		let Some(step) = self.history.redo().cloned() else {
+			self.status.info("Nothing to redo");
+			return;
+		};
+		for edit in &step {
+			self.revert(edit.pos..edit.pos + edit.removed.len(), &edit.inserted);
+		}
+		let last = &step[step.len() - 1];
+		self.move_to_byte(last.pos + last.inserted.len());
+	}
+
+	/// Applies a step from the undo history without recording it again
+	fn revert(&mut self, range: Range<usize>, text: &str) {
+		self.text.replace_range(range.clone(), text);
+		self.update_lines(range, text.len());
+		self.marker = None;
+		self.unsaved_changes = !self.history.is_saved();
+	}
+
+	/// Breaks the line, copying the indentation before the cursor to the new line if `auto_indent` is set
+	fn newline(&mut self, auto_indent: bool) {
+		let line_start = self.current_line().start;
+		let before_cursor = self.text.slice(line_start..self.char_index());
+		let indent_len = if auto_indent {
+			before_cursor.len() - before_cursor.trim_start_matches([' ', '\t']).len()
+		} else {
+			0
+		};
+		let text = format!("\n{}", self.text.slice(line_start..line_start + indent_len));
+		self.insert_str(&text);
+	}
+
+	/// Inserts a typed character. With `auto_close`, an opening bracket or quote also inserts
+	/// its closing one, or wraps the selection, and a closing one steps over the same character.
+	fn type_char(&mut self, ch: char, auto_close: bool) {
+		if !auto_close || self.overwrite {
+			return self.insert_char(ch);
+		}
+		let pos = self.char_index();
+		let prev = self.text.char_before(pos);
+		let next = self.text.char_at(pos);
+		let close = closing_char(ch);
+		if let (Some(range), Some(close)) = (self.selection(), close) {
+			self.marker = None;
+			self.history.begin_group();
+			self.edit(range.end..range.end, close.encode_utf8(&mut [0; 4]));
+			self.edit(range.start..range.start, ch.encode_utf8(&mut [0; 4]));
+			self.history.end_group();
+			self.marker = Some(range.start + ch.len_utf8());
+			self.move_to_byte(range.end + ch.len_utf8());
+			return;
+		}
+		let is_quote = matches!(ch, '"' | '\'');
+		let is_closing = is_quote || matches!(ch, ')' | ']' | '}');
+		if self.selection().is_none() && is_closing && next == Some(ch) {
+			self.move_to_byte(pos + ch.len_utf8());
+			return;
+		}
+		// an apostrophe inside a word or a quote right after one is left alone
+		let after_word = prev.is_some_and(|c| c.is_alphanumeric());
+		let closable = match next {
+			None => true,
+			Some(c) => c.is_whitespace() || matches!(c, ')' | ']' | '}' | ',' | ';'),
+		};
+		match close {
+			Some(close) if self.selection().is_none() && closable && !(is_quote && after_word) => {
+				let pair = format!("{ch}{close}");
+				self.edit(pos..pos, &pair);
+				self.move_to_byte(pos + ch.len_utf8());
 			}
+			_ => self.insert_char(ch),
 		}
-		this_line.end = self.text.len();
-		self.lines.push(this_line);
 	}
 
 	fn insert_char(&mut self, ch: char) {
-		self.unsaved_changes = true;
-		self.text.insert(self.char_index(), ch);
-		self.find_lines();
-		self.move_right();
+		let mut buf = [0; 4];
+		let text = ch.encode_utf8(&mut buf);
+		let at_line_end = self.cursor.column == self.current_line().len();
+		if self.overwrite && self.selection().is_none() && !at_line_end {
+			let pos = self.char_index();
+			self.edit(pos..self.next_char_index(), text);
+			self.move_to_byte(pos + text.len());
+		} else {
+			self.insert_str(text);
+		}
+	}
+
+	/// Inserts text at the cursor, replacing the selection if there is one, and moves the cursor past it
+	pub fn insert_str(&mut self, text: &str) {
+		let range = self.take_selection();
+		self.edit(range.clone(), text);
+		self.move_to_byte(range.start + text.len());
+	}
+
+	/// The selected range, or an empty one at the cursor. Clears the selection.
+	fn take_selection(&mut self) -> Range<usize> {
+		let pos = self.char_index();
+		let range = self.selection().unwrap_or(pos..pos);
+		self.marker = None;
+		range
+	}
+
+	/// Removes the selected text, returning false if nothing was selected
+	fn delete_selection(&mut self) -> bool {
+		let range = self.take_selection();
+		if range.is_empty() {
+			return false;
+		}
+		let start = range.start;
+		self.edit(range, "");
+		self.move_to_byte(start);
+		true
+	}
+
+	/// Moves the cursor past the next occurrence of `needle`, returning false if there is none
+	pub fn find(&mut self, needle: &str) -> bool {
+		let start = self.char_index();
+		let Some(found) = self.text.find(needle, start..self.text.len()) else {
+			return false;
+		};
+		self.move_to_byte(found + needle.len());
+		true
+	}
+
+	/// Replaces the next occurrence of `from` after the cursor, or every occurrence in the buffer.
+	/// Returns how many were replaced.
+	pub fn replace(&mut self, from: &str, to: &str, all: bool) -> usize {
+		if from.is_empty() {
+			return 0;
+		}
+		if all {
+			let matches: Vec<usize> = self.text.match_indices(from).collect();
+			if !matches.is_empty() {
+				let mut text = String::with_capacity(self.text.len());
+				let mut copied = 0;
+				for &found in &matches {
+					text += &self.text.slice(copied..found);
+					text += to;
+					copied = found + from.len();
+				}
+				text += &self.text.slice(copied..self.text.len());
+				self.replace_text(text);
+			}
+			return matches.len();
+		}
+		let start = self.char_index();
+		let Some(pos) = self.text.find(from, start..self.text.len()) else {
+			return 0;
+		};
+		self.edit(pos..pos + from.len(), to);
+		self.move_to_byte(pos + to.len());
+		1
+	}
+
+	/// Indents every line touched by a selection spanning several lines, or inserts a tab
+	fn indent(&mut self) {
+		let (first, last) = self.selected_lines();
+		if first == last {
+			self.insert_tab();
+			return;
+		}
+		let indent = if self.file_settings().indent_with_spaces {
+			" ".repeat(self.tab_width())
+		} else {
+			"\t".into()
+		};
+		let changes = self.lines[first..=last]
+			.iter()
+			.filter(|line| !line.is_empty())
+			.map(|line| (line.start, 0, indent.clone()))
+			.collect();
+		self.edit_line_starts(changes);
+	}
+
+	/// Removes one level of indentation from the current or selected lines
+	fn dedent(&mut self) {
+		let (first, last) = self.selected_lines();
+		let tab_width = self.tab_width();
+		let changes = self.lines[first..=last]
+			.iter()
+			.map(|line| {
+				let text = self.text.slice(line.clone());
+				let removed = if text.starts_with('\t') {
+					1
+				} else {
+					text.bytes()
+						.take(tab_width)
+						.take_while(|&b| b == b' ')
+						.count()
+				};
+				(line.start, removed, String::new())
+			})
+			.filter(|&(_, removed, _)| removed > 0)
+			.collect();
+		self.edit_line_starts(changes);
+	}
+
+	/// Replaces the first bytes of several lines as one undo step, keeping the cursor on the same text.
+	/// Each change is a line start, the number of bytes to remove there and what to insert instead.
+	fn edit_line_starts(&mut self, changes: Vec<(usize, usize, String)>) {
+		let mut cursor = self.char_index();
+		self.history.begin_group();
+		// from the bottom up so the earlier line starts stay valid
+		for (start, removed, inserted) in changes.into_iter().rev() {
+			cursor = shift_position(cursor, start, removed, inserted.len());
+			self.edit(start..start + removed, &inserted);
+		}
+		self.history.end_group();
+		let marker = self.marker;
+		self.move_to_byte(cursor);
+		self.marker = marker;
+	}
+
+	fn insert_tab(&mut self) {
+		if self.file_settings().indent_with_spaces {
+			let width = self.tab_width();
+			let count = width - self.physical_column() % width;
+			self.insert_str(&" ".repeat(count));
+		} else {
+			self.insert_char('\t');
+		}
 	}
 
-	fn backspace(&mut self) {
+	/// Steps through tab widths 2, 4 and 8, switching between tabs and spaces after 8
+	fn cycle_indent(&mut self) {
+		let mut settings = self.file_settings().clone();
+		settings.tab_width = match settings.tab_width {
+			2 => 4,
+			4 => 8,
+			8 => {
+				settings.indent_with_spaces = !settings.indent_with_spaces;
+				2
+			}
+			_ => 2,
+		};
+		self.settings = Some(settings);
+		self.status
+			.info(format!("Indent with {}", self.indent_label()));
+	}
+
+	fn indent_label(&self) -> String {
+		let kind = if self.file_settings().indent_with_spaces {
+			"spaces"
+		} else {
+			"tabs"
+		};
+		format!("{kind} {}", self.tab_width())
+	}
+
+	/// Deletes back to the start of the previous word or forward to the end of the next one, across line breaks
+	fn delete_word(&mut self, backward: bool) {
+		let pos = self.char_index();
+		let range = if backward {
+			self.prev_word_start(pos)..pos
+		} else {
+			pos..self.next_word_end(pos)
+		};
+		if !range.is_empty() {
+			let start = range.start;
+			self.edit(range, "");
+			self.move_to_byte(start);
+		}
+	}
+
+	fn backspace(&mut self, auto_close: bool) {
+		if self.delete_selection() {
+			return;
+		}
+		let pos = self.char_index();
+		if auto_close {
+			let prev = self.text.char_before(pos);
+			let next = self.text.char_at(pos);
+			if let (Some(open), Some(close)) = (prev, next) {
+				if closing_char(open) == Some(close) {
+					let start = pos - open.len_utf8();
+					self.edit(start..pos + close.len_utf8(), "");
+					self.move_to_byte(start);
+					return;
+				}
+			}
+		}
+		let before = self.text.slice(self.current_line().start..pos);
+		let in_soft_indent = !before.is_empty() && before.bytes().all(|b| b == b' ');
+		if in_soft_indent && self.file_settings().indent_with_spaces {
+			// back to the previous tab stop
+			let count = (before.len() - 1) % self.tab_width() + 1;
+			self.edit(pos - count..pos, "");
+			self.move_to_byte(pos - count);
+			return;
+		}
 		if self.char_index() > 0 {
 			self.move_left();
-			self.text.remove(self.char_index());
-			self.find_lines();
+			self.delete();
 		}
 	}
 
 	fn delete(&mut self) {
+		if self.delete_selection() {
+			return;
+		}
 		if self.char_index() < self.text.len() {
-			self.text.remove(self.char_index());
-			self.find_lines();
+			self.edit(self.char_index()..self.next_char_index(), "");
+		}
+	}
+
+	/// Keeps the marker on the same text when `removed` bytes at `pos` are replaced by `inserted` bytes.
+	/// A marker inside the removed text moves to where it was.
+	fn shift_marker(&mut self, pos: usize, removed: usize, inserted: usize) {
+		if let Some(marker) = &mut self.marker {
+			*marker = shift_position(*marker, pos, removed, inserted);
 		}
 	}
 
 	fn selection(&self) -> Option<Range<usize>> {
 		let cursor = self.char_index();
-		self.marker
-			.map(|marker| marker.min(cursor)..(marker.max(cursor)))
+		// clamped in case an edit left the marker outside the text or inside a character
+		let mut marker = self.marker?.min(self.text.len());
+		while !self.text.is_char_boundary(marker) {
+			marker -= 1;
+		}
+		Some(marker.min(cursor)..marker.max(cursor))
+	}
+
+	fn select_all(&mut self) {
+		self.marker = Some(0);
+		self.move_to_byte(self.text.len());
 	}
 
 	fn selection_or_line(&self) -> Range<usize> {
 		self.selection().unwrap_or(self.current_line().clone())
 	}
 
-	fn copy(&mut self, config: &mut Config) {
+	/// Inserts a copy of the selection after it, or of the current line below it, leaving the clipboard alone
+	fn duplicate(&mut self) {
+		if let Some(range) = self.selection() {
+			let marker = self.marker;
+			let text = self.text.slice(range.clone()).into_owned();
+			self.edit(range.end..range.end, &text);
+			// the selection stays on the original
+			self.marker = marker;
+			return;
+		}
+		let line = self.current_line().clone();
+		let text = format!("\n{}", self.text.slice(line.clone()));
+		self.edit(line.end..line.end, &text);
+		self.cursor.line += 1;
+		self.scroll_to_cursor();
+	}
+
+	/// Removes the current line, or all lines touched by the selection, leaving the clipboard alone
+	fn delete_lines(&mut self) {
+		let (first, last) = self.selected_lines();
+		let mut start = self.lines[first].start;
+		let mut end = self.lines[last].end;
+		if last + 1 < self.lines.len() {
+			end += 1;
+		} else if first > 0 {
+			// no line follows, so the newline before goes instead
+			start -= 1;
+		}
+		let column = self.cursor.column;
+		self.edit(start..end, "");
+		self.marker = None;
+		self.cursor.line = first.min(self.lines.len() - 1);
+		self.cursor.column = column.min(self.current_line().len());
+		self.ensure_char_boundary();
+		self.scroll_to_cursor();
+	}
+
+	/// Adds the file type's line comment prefix to the current or selected lines,
+	/// or removes it if all of them already have it. Blank lines are left alone.
+	fn toggle_comment(&mut self) {
+		let comment = self.file_settings().comment.clone();
+		let prefix = comment.as_deref().unwrap_or("#");
+		let (first, last) = self.selected_lines();
+		// start of each non-blank line and of its text after the indentation
+		let lines: Vec<(usize, usize)> = self.lines[first..=last]
+			.iter()
+			.filter_map(|line| {
+				let text = self.text.slice(line.clone());
+				let content = text.trim_start();
+				let indent = text.len() - content.len();
+				(!content.is_empty()).then_some((line.start, line.start + indent))
+			})
+			.collect();
+		let Some(indent) = lines.iter().map(|(start, text)| text - start).min() else {
+			return;
+		};
+		let commented = lines
+			.iter()
+			.all(|&(_, text)| self.text.matches_at(text, prefix));
+
+		let mut cursor = self.char_index();
+		self.history.begin_group();
+		// from the bottom up so the ranges of the remaining lines stay valid
+		for &(line_start, text_start) in lines.iter().rev() {
+			let (range, text) = if commented {
+				let mut end = text_start + prefix.len();
+				if self.text.byte(end) == Some(b' ') {
+					end += 1;
+				}
+				(text_start..end, String::new())
+			} else {
+				let start = line_start + indent;
+				(start..start, format!("{prefix} "))
+			};
+			cursor = shift_position(cursor, range.start, range.len(), text.len());
+			self.edit(range, &text);
+		}
+		self.history.end_group();
+		let marker = self.marker;
+		self.move_to_byte(cursor);
+		self.marker = marker;
+	}
+
+	fn copy(&mut self, clipboard: &Clipboard) {
 		let range = self.selection_or_line();
-		let mut text = self.text[range].to_owned();
+		let mut text = self.text.slice(range).into_owned();
 		if self.marker.is_none() {
 			text += "\n";
 		}
-		config.set_clipboard(text);
+		clipboard.set(text, self.marker.is_none());
+		self.clipboard_warning(clipboard);
 	}
 
-	fn cut(&mut self, config: &mut Config) {
+	fn cut(&mut self, clipboard: &Clipboard) {
 		let range = self.selection_or_line();
 		let start = range.start;
 		let mut end = range.end;
-		let mut text = self.text[range].to_owned();
-		if self.marker.is_none() {
+		let mut text = self.text.slice(range).into_owned();
+		let line_wise = self.marker.is_none();
+		if line_wise {
 			text += "\n";
 			end += 1;
 		}
 		end = end.min(self.text.len());
-		config.set_clipboard(text);
-		self.text = self.text[..start].to_owned() + &self.text[end..];
-		self.find_lines();
+		clipboard.set(text, line_wise);
+		self.clipboard_warning(clipboard);
+		self.edit(start..end, "");
 		self.move_to_byte(start);
 		self.marker = None;
 	}
 
-	fn paste(&mut self, config: &Config) {
-		self.unsaved_changes = true;
-		let cursor = self.char_index();
-		let new_text = config.clipboard();
-		let end_pos = cursor + new_text.len();
-		self.text.insert_str(cursor, new_text);
-		self.find_lines();
-		self.move_to_byte(end_pos);
-		self.marker = None;
+	/// Pastes at the cursor, or above the current line if whole lines were copied
+	fn paste(&mut self, clipboard: &Clipboard) {
+		let entry = clipboard.get();
+		self.clipboard_warning(clipboard);
+		if entry.line_wise && self.selection().is_none() {
+			let line_start = self.current_line().start;
+			self.edit(line_start..line_start, &entry.text);
+			self.move_to_byte(line_start);
+			return;
+		}
+		let range = self.take_selection();
+		self.edit(range.clone(), &entry.text);
+		self.move_to_byte(range.start + entry.text.len());
+	}
+
+	/// Lets the user pick an older clipboard entry to paste
+	fn paste_from_history(&mut self, config: &Config, clipboard: &Clipboard) -> EditorResult {
+		let history = clipboard.history();
+		if history.is_empty() {
+			self.status.info("Clipboard is empty");
+			return Ok(());
+		}
+		let labels: Vec<String> = history
+			.iter()
+			.map(|entry| {
+				let first_line = entry.text.lines().next().unwrap_or_default();
+				let kind = if entry.line_wise { "lines" } else { "chars" };
+				let size = entry.text.chars().count();
+				format!("{} [{size} {kind}]", first_line.replace('\t', " "))
+			})
+			.collect();
+		if let Some(index) = pick("Paste from clipboard history", &labels, &config.theme)? {
+			clipboard.promote(index);
+			self.paste(clipboard);
+		}
+		Ok(())
+	}
+
+	fn clipboard_warning(&mut self, clipboard: &Clipboard) {
+		if let Some(warning) = clipboard.take_warning() {
+			self.status.error(warning);
+		}
 	}
 
 	/// Byte position of current character. May be text.len if cursor is at the end of the file
@@ -405,60 +1504,596 @@ impl Editor {
 	/// Byte position of next character.
 	/// Returns text.len if cursor is on the last character
 	fn next_char_index(&self) -> usize {
-		self.text[self.char_index()..]
-			.char_indices()
-			.nth(1)
-			.map_or(self.text.len(), |(byte, _char)| byte + self.char_index())
+		let pos = self.char_index();
+		self.text
+			.char_at(pos)
+			.map_or(self.text.len(), |ch| pos + ch.len_utf8())
 	}
 
 	/// Byte position of preceding character.
 	/// Panics if cursor is at index 0
 	fn prev_char_index(&self) -> usize {
-		self.text[..self.char_index()]
-			.char_indices()
-			.last()
-			.map(|(byte, _char)| byte)
-			.unwrap()
+		let pos = self.char_index();
+		pos - self.text.char_before(pos).unwrap().len_utf8()
+	}
+
+	fn prev_word_start(&self, pos: usize) -> usize {
+		let word_chars = &self.file_settings().word_chars;
+		prev_word_start(&self.text.slice(0..pos), pos, word_chars)
+	}
+
+	fn next_word_end(&self, pos: usize) -> usize {
+		let word_chars = &self.file_settings().word_chars;
+		pos + next_word_end(&self.text.slice(pos..self.text.len()), 0, word_chars)
 	}
 
-	/// where the cursor is rendered in the terminal output
+	fn file_settings(&self) -> &FileSettings {
+		self.settings.as_ref().unwrap_or(&FileSettings::DEFAULT)
+	}
+
+	pub fn resolve_settings(&mut self, config: &Config) {
+		let first_line = self.text.slice(self.lines[0].clone());
+		self.settings = Some(config.file_settings(self.path.as_deref(), &first_line));
+		// the file type decides the highlighting
+		self.last_frame = None;
+	}
+
+	/// Takes over a changed global `trim_trailing_whitespace`, unless the file type sets its own.
+	/// The other settings stay as they are.
+	pub fn follow_trim_setting(&mut self, config: &Config) {
+		// settings not resolved yet will be when the editor is entered
+		let Some(settings) = &mut self.settings else {
+			return;
+		};
+		if !config.overrides_trim(settings.filetype.as_deref()) {
+			settings.trim_trailing_whitespace = config.trim_trailing_whitespace;
+		}
+	}
+
+	/// Swaps in a new version of the whole text, keeping the cursor as close to where it was as possible
+	fn replace_text(&mut self, text: String) {
+		// only the part that differs is recorded, so undo restores just that
+		let prefix = common_prefix(self.text.bytes(), text.bytes());
+		if prefix == text.len() && prefix == self.text.len() {
+			return;
+		}
+		let max_suffix = self.text.len().min(text.len()) - prefix;
+		let suffix = common_prefix(self.text.bytes().rev(), text.bytes().rev()).min(max_suffix);
+		// shrink to char boundaries so both sides stay valid strings
+		let (mut start, mut old_end, mut new_end) =
+			(prefix, self.text.len() - suffix, text.len() - suffix);
+		while !self.text.is_char_boundary(start) || !text.is_char_boundary(start) {
+			start -= 1;
+		}
+		while !self.text.is_char_boundary(old_end) || !text.is_char_boundary(new_end) {
+			old_end += 1;
+			new_end += 1;
+		}
+		self.edit(start..old_end, &text[start..new_end]);
+		self.marker = None;
+		self.cursor.line = self.cursor.line.min(self.lines.len() - 1);
+		self.cursor.column = self.cursor.column.min(self.current_line().len());
+		self.ensure_char_boundary();
+		self.scroll_to_cursor();
+	}
+
+	fn tab_width(&self) -> usize {
+		self.file_settings().tab_width
+	}
+
+	/// Screen column of the cursor, counting wide characters as two cells and combining marks as none
 	fn physical_column(&self) -> usize {
 		let start = self.current_line().start;
 		let end = self.char_index();
-		let preceding_chars = self.text[start..end].chars().count();
-		let preceding_tabs = self.text[start..end].chars().filter(|&c| c == '\t').count();
-		preceding_chars + preceding_tabs * (TAB_SIZE - 1)
+		self.text
+			.slice(start..end)
+			.chars()
+			.map(|ch| self.char_width(ch))
+			.sum()
+	}
+
+	/// Number of cells a character takes up when drawn
+	fn char_width(&self, ch: char) -> usize {
+		if ch == '\t' {
+			self.tab_width()
+		} else {
+			ch.width().unwrap_or(0)
+		}
 	}
 
-	fn save(&mut self) -> EditorResult {
+	fn save(&mut self, config: &mut Config) -> EditorResult {
+		match self.path.clone() {
+			Some(path) => self.save_to(path, config),
+			None => self.save_as(config),
+		}
+	}
+
+	/// Asks for a new path to save to, which the buffer keeps if writing succeeds
+	fn save_as(&mut self, config: &mut Config) -> EditorResult {
 		let cwd = env::current_dir().map_err(|_| Error::CurrentDir)?;
-		let Some(path) = self.path
-			.clone()
-			.or_else(|| read_line("Enter path: ")
-				.map(|s| cwd.join(s))
-			) else { return Ok(()); };
+		let Some(input) = read_path("Save as: ", Some(&mut config.path_history))? else {
+			return Ok(());
+		};
+		let path = cwd.join(input);
+		if path.exists() && self.path.as_ref() != Some(&path) {
+			let prompt = format!("'{}' already exists, overwrite?", path.display());
+			if !ask_yes_no(&prompt, false)? {
+				return Ok(());
+			}
+		}
+		self.save_to(path, config)
+	}
 
-		let mut file = File::create(&path).map_err(|e| Error::CreatingFile(path.to_owned(), e))?;
-		file.write_all(self.text.as_bytes())
-			.map_err(Error::WritingToFile)?;
+	fn save_to(&mut self, path: PathBuf, config: &Config) -> EditorResult {
+		if self.lossy {
+			let prompt =
+				"Invalid UTF-8 was replaced when opening, saving changes those bytes. Save anyway?";
+			if !ask_yes_no(prompt, false)? {
+				return Ok(());
+			}
+		}
+		let mut deleted = false;
+		if self.path.as_ref() == Some(&path) && self.disk_state.is_some() {
+			let current = disk_state(&path);
+			deleted = current.is_none();
+			if !deleted
+				&& current != self.disk_state
+				&& !ask_yes_no("File changed on disk, overwrite?", false)?
+			{
+				return Ok(());
+			}
+		}
+		let saved = self.write_to(path.clone(), config)?;
+		let mut message = if deleted {
+			format!(
+				"File was deleted on disk, created it again as '{}'",
+				path.display()
+			)
+		} else {
+			format!("Saved file as '{}'", path.display())
+		};
+		match saved.trimmed_lines {
+			0 => (),
+			1 => message += ", trimmed 1 line",
+			n => message += &format!(", trimmed {n} lines"),
+		}
+		if saved.added_newline {
+			message += ", added final newline";
+		}
+		if let Some(err) = saved.not_atomic {
+			message += &format!(" (written in place: {err})");
+		}
+		self.status.info(message);
+		Ok(())
+	}
+
+	/// Writes the buffer to `path`, which becomes the buffer's file
+	pub fn write_to(&mut self, path: PathBuf, config: &Config) -> Result<Saved, Error> {
+		let trimmed_lines = self.trim_for_saving();
+		let added_newline = config.final_newline
+			&& self
+				.text
+				.char_before(self.text.len())
+				.is_some_and(|c| c != '\n');
+		if added_newline {
+			let end = self.text.len();
+			self.edit(end..end, "\n");
+		}
+		let not_atomic = self.write_file(path, config)?;
+		Ok(Saved {
+			trimmed_lines,
+			added_newline,
+			not_atomic,
+		})
+	}
+
+	/// Saves without asking anything or trimming lines, which would move text around while typing.
+	/// Buffers without a path or that can't be saved silently, like when the file changed on disk, are skipped.
+	fn autosave(&mut self, config: &Config) {
+		let Some(path) = self.path.clone() else {
+			return;
+		};
+		if !self.is_unsaved()
+			|| self.read_only
+			|| self.lossy
+			|| disk_state(&path) != self.disk_state
+		{
+			return;
+		}
+		match self.write_file(path, config) {
+			Ok(_) => self.status.info("Autosaved"),
+			Err(err) => self.status.error(format!("Autosave failed: {err}")),
+		}
+	}
 
-		self.set_message(format!("Saved file as '{}'", path.display()));
+	/// Removes trailing whitespace if enabled for the file, returning how many lines changed
+	fn trim_for_saving(&mut self) -> usize {
+		if !self.file_settings().trim_trailing_whitespace {
+			return 0;
+		}
+		let mut trimmed_lines = 0;
+		let mut text = String::with_capacity(self.text.len());
+		for (i, line) in self.lines.iter().enumerate() {
+			let line = self.text.slice(line.clone());
+			let trimmed = line.trim_end_matches([' ', '\t']);
+			trimmed_lines += usize::from(trimmed.len() < line.len());
+			if i > 0 {
+				text.push('\n');
+			}
+			text += trimmed;
+		}
+		if trimmed_lines > 0 {
+			self.replace_text(text);
+		}
+		trimmed_lines
+	}
+
+	/// Writes the text to `path`, which becomes the buffer's file.
+	/// Returns why the file was not replaced atomically if it was written in place.
+	fn write_file(&mut self, path: PathBuf, config: &Config) -> Result<Option<Error>, Error> {
+		// a symlink stays a link to the file that is written
+		let target = path.canonicalize().unwrap_or_else(|_| path.clone());
+		let not_atomic = match write_atomically(&target, &self.text) {
+			Ok(()) => None,
+			Err(err) if can_write_in_place(&err) => Some(err),
+			// the original is still intact, e.g. when the disk is full
+			Err(err) => return Err(err),
+		};
+		if not_atomic.is_some() {
+			let mut file =
+				File::create(&target).map_err(|e| Error::CreatingFile(path.to_owned(), e))?;
+			self.text
+				.write_to(&mut file)
+				.map_err(Error::WritingToFile)?;
+		}
+
+		log!("saved {} bytes to {}", self.text.len(), path.display());
+		let path = canonical_path(&path);
+		let renamed = self.path.as_ref() != Some(&path);
 		self.path = Some(path);
 		self.unsaved_changes = false;
+		self.lossy = false;
+		self.disk_state = disk_state(self.path.as_ref().unwrap());
+		self.history.mark_saved();
+		if renamed {
+			// the new name may select different file type settings
+			self.resolve_settings(config);
+		}
+		Ok(not_atomic)
+	}
+
+	/// Pipes the buffer through the formatter configured for its file type
+	fn format(&mut self) {
+		let Some(command) = self.file_settings().format.clone() else {
+			self.status
+				.info("No formatter configured for this file type");
+			return;
+		};
+		match run_filter(&command, &self.text.to_string()) {
+			Ok(text) => {
+				self.replace_text(text);
+				self.status.info(format!("Formatted with '{command}'"));
+			}
+			Err(err) => self.status.error(format!("Formatter failed: {err}")),
+		}
+	}
+
+	fn find_prompt(&mut self, config: &mut Config) -> EditorResult {
+		if let Some(query) = read_line("Find: ", Some(&mut config.search_history))? {
+			if !query.is_empty() {
+				self.find_from(&query, self.char_index(), false);
+			}
+		}
 		Ok(())
 	}
 
-	fn go_to_line(&mut self) {
-		let max = self.lines.len();
-		let prompt = format!("Go to line (1-{max}): ");
-		if let Some(target) = read_line(&prompt).and_then(|t| t.parse::<usize>().ok()) {
-			if (1..=max).contains(&target) {
-				self.cursor.line = target - 1;
-				self.cursor.column = 0;
-				self.scroll_to_cursor();
+	/// Repeats the latest search, starting next to the cursor
+	fn find_again(&mut self, config: &Config, backward: bool) {
+		let Some(query) = config.search_history.get(0).map(str::to_owned) else {
+			self.status.info("Nothing searched for yet");
+			return;
+		};
+		let from = if backward {
+			self.char_index()
+		} else {
+			self.next_char_index()
+		};
+		self.find_from(&query, from, backward);
+	}
+
+	/// Moves the cursor to the start of the nearest match after (or before) byte `from`,
+	/// wrapping around at the end of the buffer
+	fn find_from(&mut self, query: &str, from: usize, backward: bool) {
+		let all = 0..self.text.len();
+		let found = if backward {
+			self.text.rfind(query, 0..from)
+		} else {
+			self.text.find(query, from..all.end)
+		};
+		let found = found.or_else(|| {
+			if backward {
+				self.text.rfind(query, all)
 			} else {
-				self.set_message(format!("Line {target} not in range 1-{max}"));
+				self.text.find(query, all)
+			}
+		});
+		let Some(pos) = found else {
+			self.status.error(format!("'{query}' not found"));
+			return;
+		};
+		let matches: Vec<usize> = self.text.match_indices(query).collect();
+		let index = matches.iter().take_while(|&&i| i < pos).count() + 1;
+		self.marker = None;
+		self.move_to_byte(pos);
+		self.highlight = Some(pos..pos + query.len());
+		self.status.info(format!("Match {index}/{}", matches.len()));
+	}
+
+	fn replace_prompt(&mut self, config: &mut Config) -> EditorResult {
+		let Some(pattern) = read_line("Replace: ", Some(&mut config.search_history))? else {
+			return Ok(());
+		};
+		if pattern.is_empty() {
+			return Ok(());
+		}
+		let prompt = format!("Replace '{pattern}' with: ");
+		let Some(replacement) = read_line(&prompt, Some(&mut config.replace_history))? else {
+			return Ok(());
+		};
+		self.marker = None;
+		self.history.begin_group();
+		let result = self.replace_matches(config, &pattern, &replacement);
+		self.history.end_group();
+		self.highlight = None;
+		match result? {
+			0 => self.status.info("Nothing replaced"),
+			1 => self.status.info("Replaced 1 match"),
+			count => self.status.info(format!("Replaced {count} matches")),
+		}
+		Ok(())
+	}
+
+	/// Asks about each match from the cursor to the end of the buffer, then from the start back to the cursor.
+	/// Returns the number of replacements.
+	fn replace_matches(
+		&mut self,
+		config: &Config,
+		pattern: &str,
+		replacement: &str,
+	) -> io::Result<usize> {
+		let origin = self.char_index();
+		let mut pos = origin;
+		// after wrapping around, matches are only looked for up to where the cursor started
+		let mut stop = None;
+		let mut replace_all = false;
+		let mut count = 0;
+		loop {
+			let end = stop.unwrap_or(self.text.len());
+			let Some(found) = self.text.find(pattern, pos..end) else {
+				if stop.is_some() || origin == 0 {
+					break;
+				}
+				stop = Some(origin);
+				pos = 0;
+				continue;
+			};
+			if !replace_all {
+				self.move_to_byte(found);
+				self.highlight = Some(found..found + pattern.len());
+				self.draw(config)?;
+				match ask_choice("Replace this match?", &["Yes", "No", "All", "Quit"], 0)? {
+					Some(0) => (),
+					Some(1) => {
+						pos = found + pattern.len();
+						continue;
+					}
+					Some(2) => replace_all = true,
+					_ => break,
+				}
+			}
+			// continuing after the inserted text means a replacement containing the pattern is not matched again
+			self.edit(found..found + pattern.len(), replacement);
+			pos = found + replacement.len();
+			stop = stop.map(|stop| stop + replacement.len() - pattern.len());
+			self.move_to_byte(pos);
+			count += 1;
+		}
+		Ok(count)
+	}
+
+	fn go_to_line(&mut self, config: &mut Config) -> EditorResult {
+		let max = self.lines.len();
+		let prompt = format!("Go to line (1-{max}), optionally line:column: ");
+		let Some(input) = read_line(&prompt, Some(&mut config.goto_history))? else {
+			return Ok(());
+		};
+		// also accepts `:12` and `12:5` as in compiler messages
+		let target = input.trim().trim_start_matches(':');
+		let (line, column) = match target.split_once(':') {
+			Some((line, column)) => (line, Some(column)),
+			None => (target, None),
+		};
+		let column = column.map(str::parse::<usize>).transpose();
+		let (Ok(line), Ok(column)) = (line.parse::<usize>(), column) else {
+			self.status
+				.error(format!("'{input}' is not a line number or line:column"));
+			return Ok(());
+		};
+		// out of range numbers go to the nearest line instead of failing
+		_ = self.go_to(line.clamp(1, max));
+		if let Some(column) = column {
+			self.go_to_column(column);
+		}
+		Ok(())
+	}
+
+	/// Moves the cursor to a character on the current line, counting from 1 and stopping at the line end
+	pub fn go_to_column(&mut self, column: usize) {
+		let line = self.current_line().clone();
+		self.cursor.column = self
+			.text
+			.slice(line)
+			.char_indices()
+			.nth(column.saturating_sub(1))
+			.map_or(self.current_line().len(), |(i, _)| i);
+	}
+
+	/// Moves the cursor to a line and column counting from 1, clamped to the text
+	pub fn go_to_position(&mut self, (line, column): Position) {
+		_ = self.go_to(line.clamp(1, self.lines.len()));
+		if let Some(column) = column {
+			self.go_to_column(column);
+		}
+	}
+
+	/// Moves the cursor to the start of a line, counting from 1
+	pub fn go_to(&mut self, line: usize) -> Result<(), String> {
+		let max = self.lines.len();
+		if !(1..=max).contains(&line) {
+			return Err(format!("Line {line} not in range 1-{max}"));
+		}
+		self.cursor.line = line - 1;
+		self.cursor.column = 0;
+		self.scroll_to_cursor();
+		Ok(())
+	}
+}
+
+/// Finds the bracket matching the one at `at`, taking nesting into account.
+/// Gives up after `MAX_BRACKET_SCAN` bytes so huge files don't slow down drawing.
+fn matching_bracket(text: &Text, at: usize) -> Option<usize> {
+	// brackets are ASCII, so comparing bytes can't match inside a multi-byte character
+	let bracket = text.byte(at)?;
+	let (other, forward) = match bracket {
+		b'(' => (b')', true),
+		b'[' => (b']', true),
+		b'{' => (b'}', true),
+		b')' => (b'(', false),
+		b']' => (b'[', false),
+		b'}' => (b'{', false),
+		_ => return None,
+	};
+	let mut depth = 0;
+	let mut i = at;
+	for _ in 0..MAX_BRACKET_SCAN {
+		if forward {
+			i += 1;
+		} else {
+			i = i.checked_sub(1)?;
+		}
+		let byte = text.byte(i)?;
+		if byte == bracket {
+			depth += 1;
+		} else if byte == other {
+			if depth == 0 {
+				return Some(i);
 			}
+			depth -= 1;
+		}
+	}
+	None
+}
+
+/// Where byte `position` ends up after `removed` bytes at `pos` are replaced by `inserted` bytes
+fn shift_position(position: usize, pos: usize, removed: usize, inserted: usize) -> usize {
+	if position >= pos + removed {
+		position - removed + inserted
+	} else {
+		position.min(pos)
+	}
+}
+
+/// Lines, words and characters, counted the way `str::lines` and `str::split_whitespace` do
+fn count_text(chars: impl Iterator<Item = char>) -> (usize, usize, usize) {
+	let (mut lines, mut words, mut count) = (0, 0, 0);
+	let mut in_word = false;
+	let mut last = '\n';
+	for ch in chars {
+		count += 1;
+		lines += usize::from(ch == '\n');
+		words += usize::from(!in_word && !ch.is_whitespace());
+		in_word = !ch.is_whitespace();
+		last = ch;
+	}
+	// a last line without a newline still counts
+	lines += usize::from(last != '\n');
+	(lines, words, count)
+}
+
+fn common_prefix(a: impl Iterator<Item = u8>, b: impl Iterator<Item = u8>) -> usize {
+	a.zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn read_text(path: &Path) -> Result<String, OpenError> {
+	let bytes = read_bytes(path)?;
+	String::from_utf8(bytes).map_err(|err| OpenError::InvalidUtf8(err.utf8_error().valid_up_to()))
+}
+
+fn disk_state(path: &Path) -> Option<DiskState> {
+	let metadata = fs::metadata(path).ok()?;
+	Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Reads a file, refusing ones that are too large or binary
+fn read_bytes(path: &Path) -> Result<Vec<u8>, OpenError> {
+	let metadata = fs::metadata(path)?;
+	if metadata.is_dir() {
+		return Err(OpenError::IsDirectory);
+	}
+	if metadata.len() > MAX_FILE_SIZE {
+		return Err(OpenError::TooLarge(metadata.len()));
+	}
+	let bytes = fs::read(path)?;
+	if bytes.contains(&0) {
+		return Err(OpenError::Binary);
+	}
+	Ok(bytes)
+}
+
+/// Runs a shell command with `input` on stdin, returning its stdout or the first line of stderr on failure
+fn run_filter(command: &str, input: &str) -> Result<String, String> {
+	let mut child = process::Command::new("sh")
+		.args(["-c", command])
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|err| err.to_string())?;
+	let mut stdin = child.stdin.take().unwrap();
+	let input = input.to_owned();
+	// written from another thread so a formatter that outputs while reading cannot deadlock
+	let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+	let output = child.wait_with_output().map_err(|err| err.to_string())?;
+	_ = writer.join();
+	if !output.status.success() {
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		let reason = stderr.lines().next().unwrap_or("no error output");
+		return Err(format!("{}: {reason}", output.status));
+	}
+	String::from_utf8(output.stdout).map_err(|_| "output is not valid UTF-8".into())
+}
+
+impl Display for OpenError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		const MB: u64 = 1024 * 1024;
+		match self {
+			OpenError::NotFound => write!(f, "file does not exist"),
+			OpenError::PermissionDenied => {
+				write!(f, "permission denied, check the file's owner and mode")
+			}
+			OpenError::IsDirectory => write!(f, "it is a directory"),
+			OpenError::InvalidUtf8(offset) => write!(
+				f,
+				"not valid UTF-8 at byte {offset}, it may be a binary file or use another encoding"
+			),
+			OpenError::TooLarge(size) => write!(
+				f,
+				"file is too large ({} MB, the limit is {} MB)",
+				size / MB,
+				MAX_FILE_SIZE / MB
+			),
+			OpenError::Binary => write!(f, "it looks like a binary file"),
+			OpenError::Other(err) => write!(f, "{err}"),
 		}
 	}
 }
@@ -469,7 +2104,220 @@ impl Display for Error {
 			Error::CreatingFile(name, err) => format!("Could not create {name:?}: {err}"),
 			Error::CurrentDir => "Could not get current directory".into(),
 			Error::WritingToFile(err) => format!("{err}"),
+			Error::Syncing(err) => format!("Could not flush file to disk: {err}"),
+			Error::Renaming(err) => format!("Could not replace file: {err}"),
+			Error::SavingConfig(err) => format!("Could not save settings: {err}"),
+			Error::ReadOnly => "Buffer is read-only".into(),
+			Error::Reloading(err) => format!("Could not reload file: {err}"),
+			Error::Terminal(err) => format!("Terminal error: {err}"),
 		};
 		f.write_str(&text)
 	}
 }
+
+/// The character that `auto_close` pairs with `open`
+fn closing_char(open: char) -> Option<char> {
+	match open {
+		'(' => Some(')'),
+		'[' => Some(']'),
+		'{' => Some('}'),
+		'"' => Some('"'),
+		'\'' => Some('\''),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::TestRng;
+	use crossterm::event::KeyEvent;
+	use std::sync::PoisonError;
+
+	#[test]
+	fn incremental_lines_match_a_full_scan() {
+		let pieces = ["", "x", "ö", "日本", "\n", "\n\n", "a\nβ\n", "🦀\nend"];
+		let mut rng = TestRng::new(7);
+		let mut editor = Editor::with_text("first\nsécond line\n\nlast 日本".into());
+		for _ in 0..3000 {
+			let text = editor.text.to_string();
+			let bound = |rng: &mut TestRng| {
+				let mut pos = rng.below(text.len() + 1);
+				while !text.is_char_boundary(pos) {
+					pos -= 1;
+				}
+				pos
+			};
+			// edits at the very start and end are tried often
+			let (a, b) = match rng.below(5) {
+				0 => (0, bound(&mut rng)),
+				1 => (bound(&mut rng), text.len()),
+				_ => (bound(&mut rng), bound(&mut rng)),
+			};
+			let range = a.min(b)..a.max(b);
+			editor.edit(range, pieces[rng.below(pieces.len())]);
+			let incremental = editor.lines.clone();
+			editor.find_lines();
+			assert_eq!(incremental, editor.lines);
+		}
+	}
+
+	#[test]
+	fn selection_edits_around_multibyte_text() {
+		// "é" is 2 bytes and "日" is 3, so the selection "é日" is bytes 1..6
+		let mut editor = Editor::with_text("aé日b".into());
+		editor.marker = Some(1);
+		editor.move_to_byte(6);
+		editor.insert_char('x');
+		assert_eq!(editor.text.to_string(), "axb");
+		assert_eq!((editor.char_index(), editor.marker), (2, None));
+
+		let mut editor = Editor::with_text("aé日b".into());
+		editor.marker = Some(1);
+		editor.move_to_byte(6);
+		editor.type_char('(', true);
+		assert_eq!(editor.text.to_string(), "a(é日)b");
+		assert_eq!(editor.selection(), Some(2..7));
+
+		let mut editor = Editor::with_text("aé日b".into());
+		editor.marker = Some(1);
+		editor.move_to_byte(6);
+		editor.backspace(false);
+		assert_eq!(editor.text.to_string(), "ab");
+		assert_eq!(editor.char_index(), 1);
+	}
+
+	#[test]
+	fn marker_follows_edits_around_multibyte_text() {
+		let mut editor = Editor::with_text("aé日b".into());
+		// edits before the marker move it, edits after it don't
+		editor.marker = Some(6);
+		editor.edit(0..1, "🦀");
+		assert_eq!(editor.marker, Some(9));
+		editor.edit(9..10, "ßß");
+		assert_eq!(editor.marker, Some(9));
+		assert_eq!(editor.text.slice(4..9), "é日");
+		// a marker inside removed text moves to where it was
+		editor.marker = Some(6);
+		editor.edit(4..9, "");
+		assert_eq!(editor.marker, Some(4));
+		assert_eq!(editor.text.to_string(), "🦀ßß");
+	}
+
+	#[test]
+	fn selection_snaps_marker_to_a_char_boundary() {
+		let mut editor = Editor::with_text("aé日b".into());
+		editor.move_to_byte(0);
+		// inside "日"
+		editor.marker = Some(4);
+		assert_eq!(editor.selection(), Some(0..3));
+		// past the end
+		editor.marker = Some(100);
+		assert_eq!(editor.selection(), Some(0..7));
+		editor.delete();
+		assert_eq!(editor.text.to_string(), "");
+	}
+
+	#[test]
+	fn cutting_up_to_the_top_of_the_view_scrolls_back() {
+		let _lock = screen::TEST_LOCK
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner);
+		screen::set_backend(Box::new(screen::Scripted::new([], (60, 20))));
+		let mut config = Config::new();
+		config.osc52 = false;
+		let clipboard = Clipboard::new(&config);
+		let mut editor = Editor::with_text("line\n".repeat(200));
+		editor.move_to_byte(170 * 5);
+		assert!(editor.scroll > 150);
+		// from far above the view to its first rows
+		editor.marker = Some(10 * 5);
+		editor.move_to_byte((editor.scroll + 2) * 5);
+		editor.cut(&clipboard);
+		assert_eq!(editor.cursor.line, 10);
+		assert!(editor.scroll <= editor.cursor.line);
+		editor.draw(&config).unwrap();
+		assert!(editor.scroll <= editor.cursor.line);
+	}
+
+	#[test]
+	fn toggling_trim_keeps_the_chosen_indent() {
+		let mut config = Config::new();
+		let mut editor = Editor::with_text("text".into());
+		editor.cycle_indent();
+		let tab_width = editor.tab_width();
+		config.trim_trailing_whitespace = !config.trim_trailing_whitespace;
+		editor.follow_trim_setting(&config);
+		assert_eq!(editor.tab_width(), tab_width);
+		assert_eq!(
+			editor.file_settings().trim_trailing_whitespace,
+			config.trim_trailing_whitespace
+		);
+	}
+
+	#[test]
+	fn saving_anyway_is_only_offered_when_locked_by_the_user() {
+		let _lock = screen::TEST_LOCK
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner);
+		let ctrl = |ch| Event::Key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL));
+		let events = [
+			ctrl('s'),
+			ctrl('r'),
+			ctrl('r'),
+			ctrl('s'),
+			Event::Key(KeyEvent::new(KeyCode::Char('y'), KeyModifiers::NONE)),
+		];
+		screen::set_backend(Box::new(screen::Scripted::new(events, (60, 20))));
+		let path = env::temp_dir().join(format!("lili-test-{}-locked.txt", process::id()));
+		fs::write(&path, "text\n").unwrap();
+		let mut config = Config::new();
+		config.osc52 = false;
+		let clipboard = Clipboard::new(&config);
+		let mut editor = Editor::open_file(path.clone()).unwrap();
+		// like a file that can't be written
+		editor.set_read_only(true);
+		editor.edit(0..0, "more ");
+		assert!(matches!(
+			editor.input(&mut config, &clipboard),
+			Err(Error::ReadOnly)
+		));
+		assert_eq!(fs::read_to_string(&path).unwrap(), "text\n");
+		for _ in 0..3 {
+			editor.input(&mut config, &clipboard).unwrap();
+		}
+		let saved = fs::read_to_string(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		assert_eq!(saved, "more text\n");
+	}
+
+	#[test]
+	fn physical_column_counts_cells() {
+		let mut editor = Editor::with_text("a\t日本🦀e\u{301}x".into());
+		assert_eq!(editor.tab_width(), 4);
+		// "日本" and "🦀" take two cells each, the combining accent none
+		editor.move_to_byte(editor.text.len() - 1);
+		assert_eq!(editor.physical_column(), 1 + 4 + 4 + 2 + 1);
+		editor.move_to_byte(2);
+		assert_eq!(editor.physical_column(), 5);
+		editor.move_to_byte(0);
+		assert_eq!(editor.physical_column(), 0);
+	}
+
+	#[test]
+	fn opening_searching_and_typing_keep_the_chunks() {
+		let path = env::temp_dir().join(format!("lili-test-{}-chunks.txt", process::id()));
+		fs::write(&path, "some line of text\n".repeat(100_000) + "needle\n").unwrap();
+		let mut editor = Editor::open_file(path.clone()).unwrap();
+		fs::remove_file(&path).unwrap();
+		let chunks = editor.text.chunk_addresses();
+		assert!(chunks.len() > 1);
+		assert!(editor.find("needle"));
+		assert_eq!(editor.cursor.line, 100_000);
+		editor.find_from("line", 0, true);
+		editor.move_to_byte(0);
+		editor.insert_char('x');
+		assert_eq!(editor.text.chunk_addresses()[1..], chunks[1..]);
+		assert_eq!(editor.lines.len(), 100_002);
+	}
+}
@@ -0,0 +1,154 @@
+use std::ops::Range;
+
+/// What a highlighted part of a line is, which decides its theme color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+	Comment,
+	String,
+	Keyword,
+}
+
+/// The few rules needed to find comments, strings and keywords in a line
+pub struct Language {
+	line_comment: Option<&'static str>,
+	/// Block comments are only recognised when they start and end on the same line
+	block_comment: Option<(&'static str, &'static str)>,
+	quotes: &'static [char],
+	keywords: &'static [&'static str],
+	/// Lines starting with `#` are headings, as in markdown
+	headings: bool,
+}
+
+const RUST: Language = Language {
+	line_comment: Some("//"),
+	block_comment: Some(("/*", "*/")),
+	// single quotes are left out since they are also used for lifetimes
+	quotes: &['"'],
+	keywords: &[
+		"as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+		"extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+		"mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+		"true", "type", "unsafe", "use", "where", "while",
+	],
+	headings: false,
+};
+
+const PYTHON: Language = Language {
+	line_comment: Some("#"),
+	block_comment: None,
+	quotes: &['"', '\''],
+	keywords: &[
+		"and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+		"elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+		"in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "True",
+		"try", "while", "with", "yield",
+	],
+	headings: false,
+};
+
+const C: Language = Language {
+	line_comment: Some("//"),
+	block_comment: Some(("/*", "*/")),
+	quotes: &['"', '\''],
+	keywords: &[
+		"break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum",
+		"extern", "float", "for", "goto", "if", "int", "long", "return", "short", "signed",
+		"sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void", "volatile",
+		"while", "#include", "#define", "#if", "#ifdef", "#ifndef", "#endif",
+	],
+	headings: false,
+};
+
+const TOML: Language = Language {
+	line_comment: Some("#"),
+	block_comment: None,
+	quotes: &['"', '\''],
+	keywords: &["true", "false"],
+	headings: false,
+};
+
+const MARKDOWN: Language = Language {
+	line_comment: None,
+	block_comment: None,
+	quotes: &['`'],
+	keywords: &[],
+	headings: true,
+};
+
+/// Rules for a file type key as chosen by `Config::file_settings`, if it is a known language
+pub fn language(filetype: &str) -> Option<&'static Language> {
+	match filetype {
+		"rs" => Some(&RUST),
+		"py" | "pyw" => Some(&PYTHON),
+		"c" | "h" => Some(&C),
+		"toml" => Some(&TOML),
+		"md" | "markdown" => Some(&MARKDOWN),
+		_ => None,
+	}
+}
+
+/// Finds the highlighted parts of one line, as byte ranges within it in ascending order
+pub fn line(language: &Language, text: &str) -> Vec<(Range<usize>, Kind)> {
+	let mut spans = Vec::new();
+	if language.headings && text.starts_with('#') {
+		spans.push((0..text.len(), Kind::Keyword));
+		return spans;
+	}
+	let mut pos = 0;
+	while pos < text.len() {
+		let rest = &text[pos..];
+		let ch = rest.chars().next().unwrap();
+		if language.line_comment.is_some_and(|c| rest.starts_with(c)) {
+			spans.push((pos..text.len(), Kind::Comment));
+			break;
+		}
+		if let Some((start, end)) = language.block_comment {
+			if let Some(inside) = rest.strip_prefix(start) {
+				let len = inside
+					.find(end)
+					.map_or(rest.len(), |i| start.len() + i + end.len());
+				spans.push((pos..pos + len, Kind::Comment));
+				pos += len;
+				continue;
+			}
+		}
+		if language.quotes.contains(&ch) {
+			let len = string_len(rest, ch);
+			spans.push((pos..pos + len, Kind::String));
+			pos += len;
+			continue;
+		}
+		if is_word_start(ch) {
+			let len = rest
+				.find(|c: char| !is_word_start(c) && !c.is_ascii_digit())
+				.unwrap_or(rest.len());
+			if language.keywords.contains(&&rest[..len]) {
+				spans.push((pos..pos + len, Kind::Keyword));
+			}
+			pos += len;
+			continue;
+		}
+		pos += ch.len_utf8();
+	}
+	spans
+}
+
+/// Letters, `_` and `#` so C preprocessor directives count as keywords
+fn is_word_start(ch: char) -> bool {
+	ch.is_alphabetic() || ch == '_' || ch == '#'
+}
+
+/// Length of the string starting with `quote` at the start of `text`, to the end of the line if it is not closed
+fn string_len(text: &str, quote: char) -> usize {
+	let mut escaped = false;
+	for (i, ch) in text.char_indices().skip(1) {
+		if escaped {
+			escaped = false;
+		} else if ch == '\\' {
+			escaped = true;
+		} else if ch == quote {
+			return i + ch.len_utf8();
+		}
+	}
+	text.len()
+}
@@ -0,0 +1,839 @@
+use crossterm::{
+	cursor,
+	event::{Event, KeyCode, KeyEvent, KeyModifiers},
+	queue,
+};
+use std::{
+	io::{self, Write},
+	sync::Mutex,
+};
+
+use crate::log::log;
+use crate::screen;
+use crate::theme::{Style, Theme};
+use crate::util::truncate_end;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+	Navigator,
+	Editor,
+	Prompt,
+	RecentFiles,
+}
+
+/// Something a key can be bound to; what it does depends on the context
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+	Quit,
+	Up,
+	Down,
+	Left,
+	Right,
+	PageUp,
+	PageDown,
+	Home,
+	End,
+	WordLeft,
+	WordRight,
+	MatchingBracket,
+	Accept,
+	Cancel,
+	Parent,
+	StartDir,
+	HomeDir,
+	NewEditor,
+	CloseEditor,
+	MoveEditorUp,
+	MoveEditorDown,
+	PrevEditor,
+	NextEditor,
+	SwitchEditor,
+	ToggleOverwrite,
+	ToggleReadOnly,
+	ToggleWhitespace,
+	ToggleAutoClose,
+	Statistics,
+	SaveSettings,
+	Breadcrumb,
+	CopyPath,
+	CopyName,
+	RecentFiles,
+	ToggleHidden,
+	DeleteFile,
+	RenameFile,
+	Filter,
+	CycleSort,
+	ToggleDetails,
+	GoToPath,
+	Newline,
+	Indent,
+	Dedent,
+	CycleIndent,
+	Backspace,
+	Delete,
+	DeleteWordBack,
+	DeleteWordForward,
+	Save,
+	SaveAs,
+	Copy,
+	SelectAll,
+	Duplicate,
+	DeleteLine,
+	ToggleComment,
+	Cut,
+	Paste,
+	PasteHistory,
+	GoToLine,
+	ToggleLineNumbers,
+	ToggleAutoIndent,
+	ToggleTrimWhitespace,
+	Complete,
+	Help,
+	ToggleMouse,
+	CycleTheme,
+	Format,
+	Undo,
+	Redo,
+	Find,
+	FindNext,
+	FindPrevious,
+	Replace,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Key {
+	Code(KeyModifiers, KeyCode),
+	/// Any of the digits 1 to 9
+	Digit(KeyModifiers),
+}
+
+pub struct Binding {
+	pub keys: &'static [Key],
+	pub command: Command,
+	pub description: &'static str,
+}
+
+/// A key rebound from the config file, to a command or to nothing
+#[derive(Debug, Clone, Copy)]
+pub struct Override {
+	context: Context,
+	modifiers: KeyModifiers,
+	code: KeyCode,
+	command: Option<Command>,
+}
+
+/// Checked before the default bindings, set once the config is loaded
+static OVERRIDES: Mutex<Vec<Override>> = Mutex::new(Vec::new());
+
+const NONE: KeyModifiers = KeyModifiers::NONE;
+const SHIFT: KeyModifiers = KeyModifiers::SHIFT;
+const CTRL: KeyModifiers = KeyModifiers::CONTROL;
+const CTRL_SHIFT: KeyModifiers = CTRL.union(SHIFT);
+const ALT: KeyModifiers = KeyModifiers::ALT;
+
+const fn key(code: KeyCode) -> Key {
+	Key::Code(NONE, code)
+}
+
+const fn char(ch: char) -> Key {
+	Key::Code(NONE, KeyCode::Char(ch))
+}
+
+const fn ctrl(ch: char) -> Key {
+	Key::Code(CTRL, KeyCode::Char(ch))
+}
+
+const fn bind(keys: &'static [Key], command: Command, description: &'static str) -> Binding {
+	Binding {
+		keys,
+		command,
+		description,
+	}
+}
+
+const NAVIGATOR: &[Binding] = &[
+	bind(&[key(KeyCode::Up)], Command::Up, "Select previous entry"),
+	bind(&[key(KeyCode::Down)], Command::Down, "Select next entry"),
+	bind(
+		&[key(KeyCode::Enter)],
+		Command::Accept,
+		"Open file, directory or editor",
+	),
+	bind(
+		&[key(KeyCode::Right)],
+		Command::Right,
+		"Open directory, or jump from editors to files",
+	),
+	bind(
+		&[key(KeyCode::Left), key(KeyCode::Backspace)],
+		Command::Parent,
+		"Go to parent directory",
+	),
+	bind(
+		&[key(KeyCode::PageUp)],
+		Command::PageUp,
+		"Select entry a screen up",
+	),
+	bind(
+		&[key(KeyCode::PageDown)],
+		Command::PageDown,
+		"Select entry a screen down",
+	),
+	bind(&[key(KeyCode::Home)], Command::Home, "Select first entry"),
+	bind(&[key(KeyCode::End)], Command::End, "Select last entry"),
+	bind(
+		&[char('-')],
+		Command::StartDir,
+		"Go back to the starting directory",
+	),
+	bind(&[char('~')], Command::HomeDir, "Go to home directory"),
+	bind(
+		&[char('g')],
+		Command::GoToPath,
+		"Go to a typed directory, or open a typed file",
+	),
+	bind(
+		&[Key::Digit(ALT)],
+		Command::Breadcrumb,
+		"Jump to a parent directory in the path",
+	),
+	bind(&[ctrl('n')], Command::NewEditor, "New untitled editor"),
+	bind(
+		&[char('d'), ctrl('w')],
+		Command::CloseEditor,
+		"Close selected editor",
+	),
+	bind(
+		&[Key::Code(SHIFT, KeyCode::Up), Key::Code(CTRL, KeyCode::Up)],
+		Command::MoveEditorUp,
+		"Move selected editor up in the list",
+	),
+	bind(
+		&[
+			Key::Code(SHIFT, KeyCode::Down),
+			Key::Code(CTRL, KeyCode::Down),
+		],
+		Command::MoveEditorDown,
+		"Move selected editor down in the list",
+	),
+	bind(
+		&[ctrl('s')],
+		Command::SaveSettings,
+		"Save settings to the config file",
+	),
+	bind(
+		&[char('y')],
+		Command::CopyPath,
+		"Copy path of selected entry",
+	),
+	bind(
+		&[Key::Code(SHIFT, KeyCode::Char('y'))],
+		Command::CopyName,
+		"Copy file name of selected entry",
+	),
+	bind(&[char('o')], Command::RecentFiles, "Recently opened files"),
+	bind(
+		&[char('/')],
+		Command::Filter,
+		"Filter entries by typing part of the name, Esc to clear",
+	),
+	bind(
+		&[char('s')],
+		Command::CycleSort,
+		"Sort by name, modification time or size",
+	),
+	bind(
+		&[char('i')],
+		Command::ToggleDetails,
+		"Show size and modification time of entries",
+	),
+	bind(
+		&[char('h'), char('.')],
+		Command::ToggleHidden,
+		"Show or hide dotfiles",
+	),
+	bind(
+		&[key(KeyCode::Delete)],
+		Command::DeleteFile,
+		"Delete selected file or directory",
+	),
+	bind(
+		&[char('r')],
+		Command::RenameFile,
+		"Rename or move selected file or directory",
+	),
+	bind(
+		&[key(KeyCode::F(1)), char('?')],
+		Command::Help,
+		"Show this help",
+	),
+	bind(
+		&[key(KeyCode::F(2))],
+		Command::ToggleMouse,
+		"Toggle mouse capture",
+	),
+	bind(
+		&[char('t')],
+		Command::CycleTheme,
+		"Switch to the next color theme",
+	),
+	bind(&[char('q')], Command::Quit, "Quit"),
+];
+
+const EDITOR: &[Binding] = &[
+	bind(
+		&[key(KeyCode::Esc)],
+		Command::Cancel,
+		"Back to the navigator",
+	),
+	bind(
+		&[Key::Code(CTRL, KeyCode::PageUp)],
+		Command::PrevEditor,
+		"Switch to previous open editor",
+	),
+	bind(
+		&[Key::Code(CTRL, KeyCode::PageDown)],
+		Command::NextEditor,
+		"Switch to next open editor",
+	),
+	bind(
+		&[Key::Digit(ALT)],
+		Command::SwitchEditor,
+		"Switch to open editor by number",
+	),
+	bind(&[key(KeyCode::Left)], Command::Left, "Move left"),
+	bind(&[key(KeyCode::Right)], Command::Right, "Move right"),
+	bind(&[key(KeyCode::Up)], Command::Up, "Move up"),
+	bind(&[key(KeyCode::Down)], Command::Down, "Move down"),
+	bind(
+		&[key(KeyCode::PageUp)],
+		Command::PageUp,
+		"Move up one screen",
+	),
+	bind(
+		&[key(KeyCode::PageDown)],
+		Command::PageDown,
+		"Move down one screen",
+	),
+	bind(
+		&[key(KeyCode::Home)],
+		Command::Home,
+		"Move to start of line",
+	),
+	bind(&[key(KeyCode::End)], Command::End, "Move to end of line"),
+	bind(
+		&[Key::Code(CTRL, KeyCode::Left)],
+		Command::WordLeft,
+		"Move to previous word",
+	),
+	bind(
+		&[Key::Code(CTRL, KeyCode::Right)],
+		Command::WordRight,
+		"Move to next word",
+	),
+	bind(
+		// Ctrl+] arrives as Ctrl+5 from most terminals
+		&[
+			ctrl(']'),
+			ctrl('5'),
+			Key::Code(CTRL_SHIFT, KeyCode::Char(']')),
+		],
+		Command::MatchingBracket,
+		"Move to matching bracket",
+	),
+	bind(
+		&[key(KeyCode::Enter)],
+		Command::Newline,
+		"Insert line break",
+	),
+	bind(
+		&[key(KeyCode::Insert)],
+		Command::ToggleOverwrite,
+		"Toggle between inserting and overwriting typed characters",
+	),
+	bind(
+		&[key(KeyCode::Tab)],
+		Command::Indent,
+		"Insert tab, or indent selected lines",
+	),
+	bind(
+		&[Key::Code(SHIFT, KeyCode::BackTab)],
+		Command::Dedent,
+		"Unindent line or selected lines",
+	),
+	bind(
+		&[ctrl('t')],
+		Command::CycleIndent,
+		"Cycle indent width and tabs/spaces",
+	),
+	bind(
+		&[key(KeyCode::Backspace)],
+		Command::Backspace,
+		"Delete character before cursor",
+	),
+	bind(
+		&[key(KeyCode::Delete)],
+		Command::Delete,
+		"Delete character after cursor",
+	),
+	bind(
+		&[
+			Key::Code(CTRL, KeyCode::Backspace),
+			Key::Code(ALT, KeyCode::Backspace),
+		],
+		Command::DeleteWordBack,
+		"Delete word before cursor",
+	),
+	bind(
+		&[Key::Code(CTRL, KeyCode::Delete)],
+		Command::DeleteWordForward,
+		"Delete word after cursor",
+	),
+	bind(&[ctrl('s')], Command::Save, "Save"),
+	bind(
+		&[Key::Code(CTRL_SHIFT, KeyCode::Char('s'))],
+		Command::SaveAs,
+		"Save under a new name",
+	),
+	bind(&[ctrl('a')], Command::SelectAll, "Select all"),
+	bind(&[ctrl('c')], Command::Copy, "Copy selection or line"),
+	bind(&[ctrl('x')], Command::Cut, "Cut selection or line"),
+	bind(&[ctrl('v')], Command::Paste, "Paste"),
+	bind(
+		&[ctrl('d')],
+		Command::Duplicate,
+		"Duplicate selection or line",
+	),
+	bind(
+		&[ctrl('k'), Key::Code(CTRL_SHIFT, KeyCode::Char('k'))],
+		Command::DeleteLine,
+		"Delete line or selected lines",
+	),
+	bind(
+		// most terminals send Ctrl+/ as the same byte as Ctrl+7
+		&[ctrl('/'), ctrl('_'), ctrl('7')],
+		Command::ToggleComment,
+		"Comment or uncomment lines",
+	),
+	bind(
+		&[Key::Code(CTRL_SHIFT, KeyCode::Char('v'))],
+		Command::PasteHistory,
+		"Paste from clipboard history",
+	),
+	bind(&[ctrl('z')], Command::Undo, "Undo"),
+	bind(
+		&[ctrl('y'), Key::Code(CTRL_SHIFT, KeyCode::Char('z'))],
+		Command::Redo,
+		"Redo",
+	),
+	bind(&[ctrl('f')], Command::Find, "Find"),
+	bind(&[key(KeyCode::F(3))], Command::FindNext, "Find next match"),
+	bind(
+		&[Key::Code(SHIFT, KeyCode::F(3))],
+		Command::FindPrevious,
+		"Find previous match",
+	),
+	bind(&[ctrl('h')], Command::Replace, "Find and replace"),
+	bind(&[ctrl('g')], Command::GoToLine, "Go to line"),
+	bind(
+		&[Key::Code(ALT, KeyCode::Char('f'))],
+		Command::Format,
+		"Format with the file type's formatter",
+	),
+	bind(
+		&[ctrl('l')],
+		Command::ToggleLineNumbers,
+		"Toggle line numbers",
+	),
+	bind(
+		&[Key::Code(ALT, KeyCode::Char('i'))],
+		Command::ToggleAutoIndent,
+		"Toggle auto-indent",
+	),
+	bind(
+		&[Key::Code(ALT, KeyCode::Char('w'))],
+		Command::ToggleTrimWhitespace,
+		"Toggle trimming trailing whitespace on save",
+	),
+	bind(
+		&[ctrl('r')],
+		Command::ToggleReadOnly,
+		"Toggle read-only, which prevents accidental edits",
+	),
+	bind(
+		&[
+			Key::Code(CTRL_SHIFT, KeyCode::Char('w')),
+			key(KeyCode::F(4)),
+		],
+		Command::ToggleWhitespace,
+		"Show or hide tabs and trailing spaces",
+	),
+	bind(
+		&[Key::Code(ALT, KeyCode::Char('b'))],
+		Command::ToggleAutoClose,
+		"Toggle closing brackets and quotes automatically",
+	),
+	bind(
+		&[
+			Key::Code(ALT, KeyCode::Char('c')),
+			Key::Code(CTRL_SHIFT, KeyCode::Char('c')),
+		],
+		Command::Statistics,
+		"Count lines, words and characters of the file or selection",
+	),
+	bind(&[key(KeyCode::F(1))], Command::Help, "Show this help"),
+	bind(
+		&[key(KeyCode::F(2))],
+		Command::ToggleMouse,
+		"Toggle mouse capture",
+	),
+];
+
+const PROMPT: &[Binding] = &[
+	bind(&[key(KeyCode::Enter)], Command::Accept, "Accept"),
+	bind(&[key(KeyCode::Esc)], Command::Cancel, "Cancel"),
+	bind(&[key(KeyCode::Left)], Command::Left, "Move left"),
+	bind(&[key(KeyCode::Right)], Command::Right, "Move right"),
+	bind(
+		&[Key::Code(CTRL, KeyCode::Left)],
+		Command::WordLeft,
+		"Move to previous word",
+	),
+	bind(
+		&[Key::Code(CTRL, KeyCode::Right)],
+		Command::WordRight,
+		"Move to next word",
+	),
+	bind(&[key(KeyCode::Home)], Command::Home, "Move to start"),
+	bind(&[key(KeyCode::End)], Command::End, "Move to end"),
+	bind(
+		&[key(KeyCode::Backspace)],
+		Command::Backspace,
+		"Delete character before cursor",
+	),
+	bind(
+		&[key(KeyCode::Delete)],
+		Command::Delete,
+		"Delete character after cursor",
+	),
+	bind(
+		&[key(KeyCode::Up)],
+		Command::Up,
+		"Previous entry from history",
+	),
+	bind(
+		&[key(KeyCode::Down)],
+		Command::Down,
+		"Next entry from history",
+	),
+	bind(
+		&[key(KeyCode::Tab)],
+		Command::Complete,
+		"Complete file name (twice to list matches)",
+	),
+];
+
+const RECENT_FILES: &[Binding] = &[
+	bind(&[key(KeyCode::Up)], Command::Up, "Select previous file"),
+	bind(&[key(KeyCode::Down)], Command::Down, "Select next file"),
+	bind(
+		&[key(KeyCode::PageUp)],
+		Command::PageUp,
+		"Select file a screen up",
+	),
+	bind(
+		&[key(KeyCode::PageDown)],
+		Command::PageDown,
+		"Select file a screen down",
+	),
+	bind(&[key(KeyCode::Home)], Command::Home, "Select first file"),
+	bind(&[key(KeyCode::End)], Command::End, "Select last file"),
+	bind(
+		&[key(KeyCode::Enter)],
+		Command::Accept,
+		"Open selected file",
+	),
+	bind(
+		&[key(KeyCode::Delete), char('d')],
+		Command::Delete,
+		"Remove selected file from the list",
+	),
+	bind(
+		&[key(KeyCode::Esc), char('q')],
+		Command::Cancel,
+		"Back to the navigator",
+	),
+	bind(&[key(KeyCode::F(1))], Command::Help, "Show this help"),
+];
+
+pub fn bindings(context: Context) -> &'static [Binding] {
+	match context {
+		Context::Navigator => NAVIGATOR,
+		Context::Editor => EDITOR,
+		Context::Prompt => PROMPT,
+		Context::RecentFiles => RECENT_FILES,
+	}
+}
+
+/// Finds the command bound to a key event.
+/// Shift is ignored on keys that are not bound with it, so that Shift+arrows still move.
+pub fn lookup(context: Context, event: &KeyEvent) -> Option<Command> {
+	let command = find_command(context, event);
+	log!(
+		"key {:?} {:?} in {context:?}: {command:?}",
+		event.modifiers,
+		event.code
+	);
+	command
+}
+
+fn find_command(context: Context, event: &KeyEvent) -> Option<Command> {
+	let (modifiers, code) = normalize(event);
+	let overrides = OVERRIDES.lock().map(|o| o.clone()).unwrap_or_default();
+	let find = |modifiers: KeyModifiers| {
+		let rebound = overrides
+			.iter()
+			.find(|o| o.context == context && o.modifiers == modifiers && o.code == code);
+		if let Some(rebound) = rebound {
+			return rebound.command;
+		}
+		bindings(context)
+			.iter()
+			.find(|binding| binding.keys.iter().any(|key| key.matches(modifiers, code)))
+			.map(|binding| binding.command)
+	};
+	find(modifiers).or_else(|| {
+		let is_char = matches!(code, KeyCode::Char(_));
+		(!is_char && modifiers.contains(SHIFT))
+			.then(|| find(modifiers - SHIFT))
+			.flatten()
+	})
+}
+
+/// Letters are matched in lowercase with Shift, other characters without Shift,
+/// since terminals disagree on how they report shifted characters
+fn normalize(event: &KeyEvent) -> (KeyModifiers, KeyCode) {
+	match event.code {
+		KeyCode::Char(ch) if ch.is_uppercase() => (
+			event.modifiers | SHIFT,
+			KeyCode::Char(ch.to_lowercase().next().unwrap_or(ch)),
+		),
+		KeyCode::Char(ch) if ch.is_lowercase() => (event.modifiers, event.code),
+		KeyCode::Char(_) => (event.modifiers - SHIFT, event.code),
+		_ => (event.modifiers, event.code),
+	}
+}
+
+impl Key {
+	fn matches(&self, modifiers: KeyModifiers, code: KeyCode) -> bool {
+		match *self {
+			Key::Code(m, c) => m == modifiers && c == code,
+			Key::Digit(m) => m == modifiers && matches!(code, KeyCode::Char('1'..='9')),
+		}
+	}
+
+	/// How the key is written in the help screen, like `Ctrl+S` or `Y`
+	pub fn name(&self) -> String {
+		let (modifiers, code) = match *self {
+			Key::Code(m, c) => (m, c),
+			Key::Digit(m) => (m, KeyCode::Null),
+		};
+		let mut name = String::new();
+		if modifiers.contains(CTRL) {
+			name += "Ctrl+";
+		}
+		if modifiers.contains(ALT) {
+			name += "Alt+";
+		}
+		let shift_letter = modifiers == SHIFT && matches!(code, KeyCode::Char(_));
+		if modifiers.contains(SHIFT) && !shift_letter {
+			name += "Shift+";
+		}
+		name += &match code {
+			KeyCode::Null => "1..9".into(),
+			KeyCode::Char(' ') => "Space".into(),
+			KeyCode::Char(ch) if modifiers.is_empty() => ch.to_string(),
+			KeyCode::Char(ch) => ch.to_uppercase().to_string(),
+			KeyCode::F(n) => format!("F{n}"),
+			KeyCode::PageUp => "PageUp".into(),
+			KeyCode::PageDown => "PageDown".into(),
+			KeyCode::BackTab => "Tab".into(),
+			other => format!("{other:?}"),
+		};
+		name
+	}
+}
+
+pub fn set_overrides(overrides: Vec<Override>) {
+	if let Ok(mut current) = OVERRIDES.lock() {
+		*current = overrides;
+	}
+}
+
+/// Parses a line of a `[keys.navigator]`, `[keys.editor]`, `[keys.prompt]` or `[keys.recent]` section,
+/// like `"ctrl+w" = "close_editor"`, or `"q" = "none"` to unbind a key
+pub fn parse_override(section: &str, key: &str, command: &str) -> Result<Override, String> {
+	let context = match section {
+		"navigator" => Context::Navigator,
+		"editor" => Context::Editor,
+		"prompt" => Context::Prompt,
+		"recent" => Context::RecentFiles,
+		_ => return Err(format!("unknown key section 'keys.{section}'")),
+	};
+	let key = key.trim_matches('"');
+	let (modifiers, code) = parse_key(key).ok_or_else(|| format!("unknown key '{key}'"))?;
+	let command = command.trim_matches('"');
+	let command = if command == "none" {
+		None
+	} else {
+		// commands are named like their variants in snake_case; the digit keys can't be rebound
+		let binding = bindings(context)
+			.iter()
+			.filter(|b| b.keys.iter().all(|k| matches!(k, Key::Code(..))))
+			.find(|b| command_name(b.command) == command)
+			.ok_or_else(|| format!("unknown command '{command}' in {section}"))?;
+		Some(binding.command)
+	};
+	Ok(Override {
+		context,
+		modifiers,
+		code,
+		command,
+	})
+}
+
+/// Reads keys like `ctrl+shift+k`, `alt+w`, `f5` or `pageup`, normalized the way key events are
+fn parse_key(key: &str) -> Option<(KeyModifiers, KeyCode)> {
+	let (mods, name) = match key.rsplit_once('+') {
+		Some((mods, "")) => (mods.strip_suffix('+').unwrap_or(mods), "+"),
+		Some((mods, name)) => (mods, name),
+		None => ("", key),
+	};
+	let mut modifiers = NONE;
+	for modifier in mods.split('+').filter(|m| !m.is_empty()) {
+		modifiers |= match modifier.to_lowercase().as_str() {
+			"ctrl" => CTRL,
+			"alt" => ALT,
+			"shift" => SHIFT,
+			_ => return None,
+		};
+	}
+	let mut chars = name.chars();
+	let code = match (chars.next(), chars.next()) {
+		(Some(ch), None) => KeyCode::Char(ch),
+		_ => match name.to_lowercase().as_str() {
+			"space" => KeyCode::Char(' '),
+			"enter" => KeyCode::Enter,
+			"esc" | "escape" => KeyCode::Esc,
+			"tab" => KeyCode::Tab,
+			"backspace" => KeyCode::Backspace,
+			"delete" | "del" => KeyCode::Delete,
+			"insert" => KeyCode::Insert,
+			"home" => KeyCode::Home,
+			"end" => KeyCode::End,
+			"pageup" => KeyCode::PageUp,
+			"pagedown" => KeyCode::PageDown,
+			"up" => KeyCode::Up,
+			"down" => KeyCode::Down,
+			"left" => KeyCode::Left,
+			"right" => KeyCode::Right,
+			other => KeyCode::F(other.strip_prefix('f')?.parse().ok()?),
+		},
+	};
+	let event = KeyEvent::new(code, modifiers);
+	Some(normalize(&event))
+}
+
+/// `Command::CloseEditor` is called `close_editor` in the config file
+fn command_name(command: Command) -> String {
+	let mut name = String::new();
+	for ch in format!("{command:?}").chars() {
+		if ch.is_uppercase() && !name.is_empty() {
+			name.push('_');
+		}
+		name.push(ch.to_ascii_lowercase());
+	}
+	name
+}
+
+/// Full-screen list of all keybindings; Up/Down scroll and any other key closes it
+pub fn show_help(theme: &Theme) -> io::Result<()> {
+	let mut lines = Vec::new();
+	let sections = [
+		("Navigator", Context::Navigator),
+		(
+			"Editor (hold Shift while moving to select)",
+			Context::Editor,
+		),
+		("Prompts", Context::Prompt),
+		("Recent files", Context::RecentFiles),
+	];
+	for (title, context) in sections {
+		if !lines.is_empty() {
+			lines.push((None, String::new()));
+		}
+		lines.push((None, title.to_owned()));
+		let overrides = OVERRIDES.lock().map(|o| o.clone()).unwrap_or_default();
+		let overrides: Vec<_> = overrides.iter().filter(|o| o.context == context).collect();
+		for binding in bindings(context) {
+			let default_keys = binding
+				.keys
+				.iter()
+				.filter(|key| !overrides.iter().any(|o| key.matches(o.modifiers, o.code)));
+			let rebound_keys = overrides
+				.iter()
+				.filter(|o| o.command == Some(binding.command))
+				.map(|o| Key::Code(o.modifiers, o.code));
+			let keys: Vec<_> = default_keys
+				.copied()
+				.chain(rebound_keys)
+				.map(|key| key.name())
+				.collect();
+			if !keys.is_empty() {
+				lines.push((Some(keys.join(", ")), binding.description.to_owned()));
+			}
+		}
+	}
+	let key_width = lines
+		.iter()
+		.filter_map(|(keys, _)| keys.as_ref().map(|k| k.chars().count()))
+		.max()
+		.unwrap_or(0);
+
+	let mut out = screen::out();
+	let mut scroll = 0;
+	loop {
+		let (width, height) = screen::size();
+		let (width, height) = (width as usize, height as usize);
+		let rows = height - 1;
+		scroll = scroll.min(lines.len().saturating_sub(rows));
+
+		screen::clear()?;
+		queue!(out, cursor::Hide)?;
+		for (row, (keys, text)) in lines.iter().skip(scroll).take(rows).enumerate() {
+			queue!(out, screen::move_to(0, row as u16))?;
+			let line = match keys {
+				Some(keys) => format!("  {keys:key_width$}  {text}"),
+				None => text.clone(),
+			};
+			write!(out, "{}", truncate_end(&line, width))?;
+		}
+		queue!(out, screen::move_to(0, rows as u16))?;
+		theme.status.queue(&mut out)?;
+		let hint = "Keybindings (Up/Down: scroll, any other key: close)";
+		write!(out, "{:width$}", truncate_end(hint, width))?;
+		Style::reset(&mut out)?;
+		out.flush()?;
+
+		let Event::Key(event) = screen::read_event()? else {
+			continue;
+		};
+		match event.code {
+			KeyCode::Up => scroll = scroll.saturating_sub(1),
+			KeyCode::Down => scroll += 1,
+			KeyCode::PageUp => scroll = scroll.saturating_sub(rows),
+			KeyCode::PageDown => scroll += rows,
+			_ => return Ok(()),
+		}
+	}
+}
@@ -0,0 +1,66 @@
+use std::{
+	env,
+	fmt::Arguments,
+	fs::{self, File, OpenOptions},
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::{Mutex, OnceLock},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::config::config_dir;
+
+static LOG_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Appends a timestamped line to the debug log, if there is one.
+/// The arguments are not even formatted when logging is off.
+macro_rules! log {
+	($($arg:tt)*) => {
+		if $crate::log::enabled() {
+			$crate::log::write(format_args!($($arg)*));
+		}
+	};
+}
+pub(crate) use log;
+
+/// Where to log when enabled with `LILI_LOG=debug` instead of `--log <path>`
+pub fn path_from_env() -> Option<PathBuf> {
+	let level = env::var("LILI_LOG").ok()?;
+	(level == "debug").then(|| config_dir().map(|dir| dir.join("debug.log")))?
+}
+
+/// Starts appending to the log file at `path`
+pub fn init(path: &Path) -> io::Result<()> {
+	if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+		fs::create_dir_all(dir)?;
+	}
+	let file = OpenOptions::new().create(true).append(true).open(path)?;
+	_ = LOG_FILE.set(Mutex::new(file));
+	log!("lili {} started", env!("CARGO_PKG_VERSION"));
+	Ok(())
+}
+
+pub fn enabled() -> bool {
+	LOG_FILE.get().is_some()
+}
+
+/// Writes straight to the file without buffering, so the log is complete even after a crash
+pub fn write(args: Arguments) {
+	let Some(file) = LOG_FILE.get() else {
+		return;
+	};
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default();
+	let secs = now.as_secs();
+	let line = format!(
+		"{:02}:{:02}:{:02}.{:03} {args}\n",
+		secs / 3600 % 24,
+		secs / 60 % 60,
+		secs % 60,
+		now.subsec_millis()
+	);
+	if let Ok(mut file) = file.lock() {
+		_ = file.write_all(line.as_bytes());
+	}
+}
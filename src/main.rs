@@ -1,159 +1,691 @@
 use crossterm::{
-	cursor::{self, MoveTo},
-	event::{self, Event, KeyCode, KeyModifiers},
-	execute, queue,
-	terminal::{
-		self, disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
-		LeaveAlternateScreen,
-	},
+	cursor,
+	event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
+	queue,
 };
 use std::{
-	env, fs,
-	io::{stdout, Write},
-	path::PathBuf,
+	cmp::Reverse,
+	collections::HashMap,
+	env,
+	ffi::OsStr,
+	fs,
+	io::{self, stdout, ErrorKind, IsTerminal, Read, Write},
+	panic,
+	path::{Path, PathBuf},
 	process::exit,
+	time::{Duration, Instant},
 };
 
+mod batch;
+mod cli;
+mod clipboard;
 mod config;
 mod editor;
+mod highlight;
+mod keymap;
+mod log;
+mod recent;
+mod screen;
+mod status;
+mod text;
+mod theme;
+mod undo;
 mod util;
+use cli::Args;
+use clipboard::Clipboard;
 use config::Config;
-use editor::Editor;
-use util::{ask_yes_no, color_highlight, color_reset};
+use editor::{Editor, Leave, OpenError};
+use keymap::{Command, Context};
+use log::log;
+use recent::RecentFiles;
+use status::{Severity, StatusBar};
+use theme::{Style, Theme};
+use unicode_width::UnicodeWidthStr;
+use util::{
+	ask_yes_no, edit_line, format_time, human_size, read_path, split_position, truncate_end,
+	truncate_start, Position,
+};
 
 fn main() {
-	Navigator::new().run();
+	let args = Args::parse();
+	if let Some(path) = args.log.clone().or_else(log::path_from_env) {
+		if let Err(err) = log::init(&path) {
+			eprintln!("lili: could not open log file {}: {err}", path.display());
+		}
+	}
+	set_panic_hook();
+	if let Some(script) = &args.batch {
+		batch::run(script, &args.paths);
+	}
+	if args.print {
+		print_files(&args);
+	}
+	if let Err(err) = Navigator::new(args).run() {
+		screen::fail(err);
+	}
+}
+
+/// Restores the terminal and records panics in the log before the default hook prints them,
+/// so the message is readable
+fn set_panic_hook() {
+	let default_hook = panic::take_hook();
+	panic::set_hook(Box::new(move |info| {
+		log!("panic: {info}");
+		screen::leave_after_panic();
+		default_hook(info);
+	}));
+}
+
+fn print_files(args: &Args) -> ! {
+	let (config, _) = Config::load();
+	let mut out = stdout().lock();
+	if !out.is_terminal() {
+		theme::disable_colors();
+	}
+	let mut failed = false;
+	for path in &args.paths {
+		let mut editor = match Editor::open_file(path.clone()) {
+			Ok(editor) => editor,
+			Err(err) => {
+				eprintln!("lili: {}: {err}", path.display());
+				failed = true;
+				continue;
+			}
+		};
+		let result = editor.print(&mut out, &config).and_then(|()| out.flush());
+		match result {
+			Ok(()) => (),
+			// the reader went away, e.g. when piping into `head`
+			Err(err) if err.kind() == ErrorKind::BrokenPipe => exit(0),
+			Err(err) => {
+				eprintln!("lili: {}: {err}", path.display());
+				failed = true;
+			}
+		}
+	}
+	exit(failed as i32);
+}
+
+fn open_error_message(path: &Path, err: &OpenError) -> String {
+	let name = path
+		.file_name()
+		.unwrap_or(path.as_os_str())
+		.to_string_lossy();
+	format!("Could not open '{name}': {err}")
+}
+
+/// Untitled buffer with everything read from stdin
+fn stdin_editor(read_only: bool) -> Editor {
+	// read everything before entering raw mode, crossterm reads key events from /dev/tty when stdin is not a terminal
+	let mut input = Vec::new();
+	_ = io::stdin().read_to_end(&mut input);
+	let text = String::from_utf8_lossy(&input).into_owned();
+	let mut editor = Editor::with_text(text);
+	editor.set_read_only(read_only);
+	editor
+}
+
+/// Names to show for the files of the editors, with as many parent directories as
+/// needed to tell apart files that share a name, like `src/main.rs` and `tests/main.rs`
+fn editor_names(editors: &[Editor]) -> Vec<Option<String>> {
+	// components of each path from the file name upwards
+	let paths: Vec<Vec<&OsStr>> = editors
+		.iter()
+		.map(|editor| {
+			editor
+				.path()
+				.map_or(Vec::new(), |path| path.iter().rev().collect())
+		})
+		.collect();
+	let clashes = |index: usize, depth: usize| {
+		paths.iter().enumerate().any(|(other, path)| {
+			other != index && path.len() >= depth && path[..depth] == paths[index][..depth]
+		})
+	};
+	paths
+		.iter()
+		.enumerate()
+		.map(|(index, path)| {
+			if path.is_empty() {
+				return None;
+			}
+			let mut depth = 1;
+			while depth < path.len() && clashes(index, depth) {
+				depth += 1;
+			}
+			let shown: PathBuf = path[..depth].iter().rev().collect();
+			Some(shown.to_string_lossy().into_owned())
+		})
+		.collect()
+}
+
+/// Line of an editor in the list of open editors, `name` comes from `editor_names`
+fn editor_entry(editor: &Editor, name: Option<&str>, width: usize) -> String {
+	let mut title = editor.title_with_name(name);
+	if let Some(hint) = editor.content_hint() {
+		title = format!("{title}: {}", hint.replace('\t', " "));
+	}
+	let position = format!(" {}/{}", editor.cursor_line() + 1, editor.line_count());
+	let title_width = width.saturating_sub(position.len());
+	format!("{}{position}", truncate_end(&title, title_width))
+}
+
+/// Columns taken by `entry_details`
+const DETAILS_WIDTH: usize = 28;
+
+/// Size and modification time of a listed entry, or the number of entries in a directory
+fn entry_details(path: &Path) -> String {
+	// symlinks show their target, or `?` when it is missing
+	let Ok(metadata) = fs::metadata(path) else {
+		return "?".into();
+	};
+	let size = if metadata.is_dir() {
+		fs::read_dir(path).map_or("?".into(), |entries| format!("{} items", entries.count()))
+	} else {
+		human_size(metadata.len())
+	};
+	let modified = metadata.modified().map_or("?".into(), format_time);
+	format!("{size:>10}  {modified:>16}")
+}
+
+/// Order of the entries within the directories and files groups of the listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortMode {
+	Name,
+	/// Newest first
+	Modified,
+	/// Largest first
+	Size,
+}
+
+impl SortMode {
+	fn next(self) -> Self {
+		match self {
+			Self::Name => Self::Modified,
+			Self::Modified => Self::Size,
+			Self::Size => Self::Name,
+		}
+	}
+
+	fn name(self) -> &'static str {
+		match self {
+			Self::Name => "name",
+			Self::Modified => "time",
+			Self::Size => "size",
+		}
+	}
 }
 
 struct Navigator {
 	config: Config,
+	clipboard: Clipboard,
+	recent: RecentFiles,
 	editors: Vec<Editor>,
 	files: Vec<PathBuf>,
 	selected: usize,
 	path: PathBuf,
 	init_path: PathBuf,
 	immediate_open: bool,
-	message: Option<String>,
+	/// Open every file read-only, not just the ones from the command line
+	read_only_all: bool,
+	/// Draw in the normal screen buffer below the shell prompt instead of the alternate screen
+	inline: bool,
+	status: StatusBar,
+	list_error: Option<String>,
 	scroll: usize,
+	/// Time and entry of the last click, to recognise double clicks
+	last_click: Option<(Instant, usize)>,
+	/// List entries starting with `.`
+	show_hidden: bool,
+	/// Entries left out of the listing because they are hidden
+	hidden_count: usize,
+	/// Only entries whose names contain this are listed, while typing narrows it down
+	filter: Option<String>,
+	/// Entries left out of the listing by the filter
+	filtered_count: usize,
+	/// Entry that was selected before filtering, selected again when the filter is cleared
+	filter_return: Option<PathBuf>,
+	sort: SortMode,
+	/// Show size and modification time after each entry
+	details: bool,
+	/// `entry_details` of entries drawn since the listing was last read
+	details_cache: HashMap<PathBuf, String>,
 }
 
 impl Navigator {
-	fn new() -> Self {
+	fn new(args: Args) -> Self {
 		let mut editors = Vec::new();
-		let args: Vec<String> = env::args().skip(1).collect();
-		let mut path = env::current_dir().unwrap();
+		let mut recent = RecentFiles::load();
+		let mut open_error = None;
+		let mut path = env::current_dir().unwrap_or_else(|err| {
+			eprintln!("lili: could not get current directory: {err}");
+			exit(1);
+		});
 
-		for arg in args.iter().map(PathBuf::from) {
+		for arg in &args.paths {
+			if arg.as_os_str() == "-" {
+				editors.push(stdin_editor(args.read_only));
+				continue;
+			}
+			let (arg, position) = split_position(arg);
 			if arg.is_dir() {
-				path = arg.canonicalize().unwrap();
+				path = arg.canonicalize().unwrap_or(arg);
 				break;
 			} else if arg.is_file() {
-				if let Ok(editor) = Editor::open_file(arg) {
-					editors.push(editor);
+				match Editor::open_file(arg.clone()) {
+					Ok(mut editor) => {
+						if let Some(path) = editor.path() {
+							recent.add(path.clone());
+						}
+						// files that can't be written are already read-only
+						if args.read_only {
+							editor.set_read_only(true);
+						}
+						if let Some(position) = position {
+							editor.go_to_position(position);
+						}
+						editors.push(editor);
+					}
+					Err(err) => {
+						open_error.get_or_insert(open_error_message(&arg, &err));
+					}
 				}
 			} else {
-				editors.push(Editor::new(Some(arg)));
+				let mut editor = Editor::new(Some(arg));
+				editor.set_read_only(args.read_only);
+				editors.push(editor);
 			}
 		}
-		if args.is_empty() {
-			editors.push(Editor::new(None));
+		if args.paths.is_empty() {
+			// at the end of a pipe, like `git diff | lili`
+			if io::stdin().is_terminal() {
+				editors.push(Editor::new(None));
+			} else {
+				editors.push(stdin_editor(args.read_only));
+			}
 		}
 		let immediate_open = editors.len() == 1;
+		let (config, config_warning) = Config::load();
+		let mut status = StatusBar::default();
+		if let Some(warning) = config_warning.or(open_error) {
+			status.sticky(warning, Severity::Error);
+		}
 		Self {
-			config: Config::new(),
+			clipboard: Clipboard::new(&config),
+			inline: args.inline || config.inline,
+			config,
+			recent,
 			editors,
 			selected: 0,
 			files: Vec::new(),
 			init_path: path.clone(),
 			path,
 			immediate_open,
-			message: None,
+			read_only_all: args.read_only_all,
+			status,
+			list_error: None,
 			scroll: 0,
+			last_click: None,
+			show_hidden: false,
+			hidden_count: 0,
+			filter: None,
+			filtered_count: 0,
+			filter_return: None,
+			sort: SortMode::Name,
+			details: false,
+			details_cache: HashMap::new(),
 		}
 	}
 
-	fn run(mut self) {
-		execute!(stdout(), EnterAlternateScreen, Clear(ClearType::All)).unwrap();
-		enable_raw_mode().unwrap();
+	/// Runs until the user quits. Only returns if the terminal stops working.
+	fn run(mut self) -> io::Result<()> {
+		screen::enter(self.inline)?;
+		if self.config.mouse {
+			if let Err(err) = screen::set_mouse_capture(true) {
+				self.status.sticky(
+					format!("Mouse capture unavailable, using keyboard only: {err}"),
+					Severity::Error,
+				);
+			}
+		}
 
+		self.get_files();
 		if self.immediate_open {
-			self.enter();
+			self.enter()?;
 		}
 
+		// the listing is read again after navigating or changing files, not on every key
 		loop {
-			self.get_files();
-			self.draw();
-			self.message = None;
-			self.input();
+			let result = self.draw().and_then(|()| self.input());
+			match result {
+				Err(err) if screen::is_transient(&err) => log!("retrying after: {err}"),
+				Err(err) => return Err(err),
+				Ok(()) => (),
+			}
 		}
 	}
 
-	fn draw(&self) {
-		queue!(stdout(), Clear(ClearType::All), cursor::Hide, MoveTo(0, 0)).unwrap();
-		print!("Open editors: {}", self.editors.len());
+	fn draw(&mut self) -> io::Result<()> {
+		let mut out = screen::out();
+		queue!(out, cursor::Hide, screen::move_to(0, 0))?;
+		write!(out, "Open editors: {}", self.editors.len())?;
+		screen::clear_line_end()?;
+		let width = screen::size().0;
+		let theme = &self.config.theme;
+		// a short terminal may not fit everything, rows that would reach the status bar are left out
+		let status_row = status::row();
 
-		for (index, editor) in self.editors.iter().enumerate() {
+		let fitting_editors = (status_row as usize).saturating_sub(1);
+		let names = editor_names(&self.editors);
+		for (index, editor) in self.editors.iter().enumerate().take(fitting_editors) {
+			queue!(out, screen::move_to(0, index as u16 + 1))?;
+			write!(out, " ")?;
 			if index == self.selected {
-				color_highlight();
+				theme.selection.queue(&mut out)?;
 			}
-			queue!(stdout(), MoveTo(1, index as u16 + 1)).unwrap();
-			print!("{}", editor.title());
-			color_reset();
+			let entry = editor_entry(
+				editor,
+				names[index].as_deref(),
+				(width as usize).saturating_sub(1),
+			);
+			write!(out, "{entry}")?;
+			Style::reset(&mut out)?;
+			screen::clear_line_end()?;
 		}
 
 		let offset = self.editors.len() as u16 + 2;
-		queue!(stdout(), MoveTo(0, offset)).unwrap();
-		print!("Current dir: {}", self.path.to_string_lossy());
+		if offset >= status_row {
+			screen::clear_below(fitting_editors as u16 + 1)?;
+			self.status
+				.draw(&mut out, theme, &self.summary(), "F1: help")?;
+			return out.flush();
+		}
+		queue!(out, screen::move_to(0, offset - 1))?;
+		screen::clear_line_end()?;
+		queue!(out, screen::move_to(0, offset))?;
+		let prefix = "Current dir: ";
+		let crumbs: Vec<String> = self
+			.breadcrumbs()
+			.into_iter()
+			.map(|(name, _)| name)
+			.collect();
+		let crumbs = crumbs.join(" / ");
+		let crumbs = match crumbs.trim_start() {
+			"" => "/",
+			crumbs => crumbs,
+		};
+		let hidden_state = format!(
+			"  (by {}, dotfiles {})",
+			self.sort.name(),
+			if self.show_hidden { "shown" } else { "hidden" }
+		);
+		let crumbs = truncate_start(
+			crumbs,
+			(width as usize).saturating_sub(prefix.len() + hidden_state.len()),
+		);
+		write!(out, "{prefix}{crumbs}")?;
+		theme.dimmed.queue(&mut out)?;
+		write!(out, "{hidden_state}")?;
+		Style::reset(&mut out)?;
+		screen::clear_line_end()?;
 
-		let height = terminal::size().unwrap().1;
-		let max_rows = height as usize - self.editors.len() - 4;
+		let max_rows = self.list_rows();
 		let end = (self.scroll + max_rows).min(self.files.len());
 		let visible_rows = self.scroll..end;
 
+		let mut next_row = offset + 1 + visible_rows.len() as u16;
 		for (index, path) in self.files[visible_rows].iter().enumerate() {
+			queue!(out, screen::move_to(0, index as u16 + 1 + offset))?;
+			write!(out, " ")?;
 			if index + self.scroll == self.selected.wrapping_sub(self.editors.len()) {
-				color_highlight();
+				theme.selection.queue(&mut out)?;
+			} else if path.is_dir() {
+				theme.directory.queue(&mut out)?;
 			}
-			queue!(stdout(), MoveTo(1, index as u16 + 1 + offset)).unwrap();
-			if let Some(name) = path.file_name() {
-				print!("{}", name.to_string_lossy());
-			} else {
-				print!("..");
+			let mut label = match path.file_name() {
+				Some(name) => name.to_string_lossy().into_owned(),
+				None => "..".into(),
+			};
+			if path.is_symlink() {
+				label.push('@');
+			} else if path.is_dir() {
+				label.push('/');
 			}
-			if path.is_dir() {
-				print!("/");
+			let name_width = (width as usize).saturating_sub(DETAILS_WIDTH + 3);
+			if self.details && path.file_name().is_some() && name_width >= 8 {
+				let label = truncate_end(&label, name_width);
+				let padding = name_width - label.width();
+				let details = self
+					.details_cache
+					.entry(path.clone())
+					.or_insert_with(|| entry_details(path));
+				write!(out, "{label}{:padding$}  {details:>DETAILS_WIDTH$}", "")?;
+			} else {
+				write!(out, "{label}")?;
 			}
-			color_reset();
+			Style::reset(&mut out)?;
+			screen::clear_line_end()?;
 		}
 
-		if let Some(text) = &self.message {
-			queue!(stdout(), MoveTo(0, height)).unwrap();
-			print!("{text}");
+		// only the `..` entry is listed in these cases, so the row below it is free
+		let note = if let Some(err) = &self.list_error {
+			Some((theme.error, format!("(could not list directory: {err})")))
+		} else if self.files.len() == 1 {
+			Some((theme.dimmed, "(empty directory)".to_owned()))
+		} else {
+			None
+		};
+		if let Some((style, note)) = note.filter(|_| offset + 2 < status_row) {
+			queue!(out, screen::move_to(1, offset + 2))?;
+			style.queue(&mut out)?;
+			write!(out, "{note}")?;
+			Style::reset(&mut out)?;
+			screen::clear_line_end()?;
+			next_row = offset + 3;
 		}
+		screen::clear_below(next_row)?;
 
-		stdout().flush().unwrap();
+		self.status
+			.draw(&mut out, theme, &self.summary(), "F1: help")?;
+		out.flush()
 	}
 
-	fn input(&mut self) {
-		if let Ok(Event::Key(event)) = event::read() {
-			match event.code {
-				KeyCode::Char('q') => self.quit(),
-				KeyCode::Up => self.nav_up(),
-				KeyCode::Down => self.nav_down(),
-				KeyCode::Enter => self.enter(),
-				KeyCode::Home => self.path = self.init_path.clone(),
-				KeyCode::Char('n') => {
-					if event.modifiers == KeyModifiers::CONTROL {
-						self.new_editor();
-					}
+	/// Number of entries in the current directory, for the status bar
+	fn summary(&self) -> String {
+		// not counting `..`
+		let entries = match self.files.len() - 1 {
+			1 => "1 entry".into(),
+			n => format!("{n} entries"),
+		};
+		let mut summary = match self.hidden_count + self.filtered_count {
+			0 => entries,
+			n => format!("{entries}, {n} hidden"),
+		};
+		if let Some(filter) = &self.filter {
+			summary = format!("Filter: {filter}  ({summary})");
+		}
+		summary
+	}
+
+	fn input(&mut self) -> io::Result<()> {
+		let event = match screen::read_event()? {
+			Event::Key(event) => event,
+			Event::Mouse(event) => return self.mouse(event),
+			Event::Resize(..) => {
+				self.update_scroll();
+				return Ok(());
+			}
+			_ => return Ok(()),
+		};
+		self.status.dismiss();
+		if self.filter.is_some() && self.filter_input(&event) {
+			return Ok(());
+		}
+		let Some(command) = keymap::lookup(Context::Navigator, &event) else {
+			return Ok(());
+		};
+		match command {
+			Command::Quit => self.quit()?,
+			Command::Filter => {
+				self.filter_return = self.selected_file();
+				self.filter = Some(String::new());
+			}
+			Command::Up => self.nav_up(),
+			Command::Down => self.nav_down(),
+			Command::PageUp => self.nav_page(false),
+			Command::PageDown => self.nav_page(true),
+			Command::Accept => self.enter()?,
+			Command::Parent if self.selected >= self.editors.len() => self.go_to_parent(),
+			Command::Right => {
+				if self.selected >= self.editors.len() {
+					self.enter()?;
+				} else {
+					self.selected = self.editors.len();
+					self.update_scroll();
 				}
-				_ => (),
 			}
+			Command::Home => {
+				self.selected = 0;
+				self.update_scroll();
+			}
+			Command::End => {
+				self.selected = self.editors.len() + self.files.len() - 1;
+				self.update_scroll();
+			}
+			Command::StartDir => self.set_path(self.init_path.clone()),
+			Command::HomeDir => match env::var_os("HOME") {
+				Some(home) => self.set_path(PathBuf::from(home)),
+				None => self.status.error("$HOME is not set"),
+			},
+			Command::GoToPath => self.go_to_path()?,
+			Command::NewEditor => self.new_editor()?,
+			Command::CloseEditor if self.selected < self.editors.len() => self.close_editor()?,
+			Command::MoveEditorUp => self.move_editor(false),
+			Command::MoveEditorDown => self.move_editor(true),
+			Command::SaveSettings => self.save_settings(),
+			Command::Breadcrumb => {
+				if let KeyCode::Char(ch) = event.code {
+					self.jump_to_breadcrumb(ch as usize - '1' as usize);
+				}
+			}
+			Command::CopyPath => self.copy_path(false),
+			Command::CopyName => self.copy_path(true),
+			Command::DeleteFile => self.delete_selected()?,
+			Command::RenameFile => self.rename_selected()?,
+			Command::ToggleDetails => self.details = !self.details,
+			Command::CycleSort => {
+				let selected_file = self.selected_file();
+				self.sort = self.sort.next();
+				self.get_files();
+				if let Some(file) = selected_file {
+					self.select_file(&file);
+				}
+				self.update_scroll();
+				self.status.info(format!("Sorting by {}", self.sort.name()));
+			}
+			Command::ToggleHidden => {
+				let selected_file = self.selected_file();
+				self.show_hidden = !self.show_hidden;
+				self.get_files();
+				// stay on the same entry if it is still listed
+				if !selected_file.is_some_and(|file| self.select_file(&file)) {
+					let last = self.editors.len() + self.files.len() - 1;
+					self.selected = self.selected.min(last);
+				}
+				self.update_scroll();
+			}
+			Command::RecentFiles => self.recent_files()?,
+			Command::Help => keymap::show_help(&self.config.theme)?,
+			Command::ToggleMouse => self.status.info(screen::toggle_mouse_capture()),
+			Command::CycleTheme => self.cycle_theme(),
+			_ => (),
+		}
+		Ok(())
+	}
+
+	/// Clicking selects an entry, double clicking opens it like Enter
+	fn mouse(&mut self, event: MouseEvent) -> io::Result<()> {
+		const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+		match event.kind {
+			MouseEventKind::Down(MouseButton::Left) => (),
+			MouseEventKind::ScrollUp => {
+				self.nav_up();
+				return Ok(());
+			}
+			MouseEventKind::ScrollDown => {
+				self.nav_down();
+				return Ok(());
+			}
+			_ => return Ok(()),
+		}
+		let row = screen::relative_row(event.row) as usize;
+		if row >= status::row() as usize {
+			return Ok(());
+		}
+		let files_start = self.editors.len() + 3;
+		let index = if (1..=self.editors.len()).contains(&row) {
+			row - 1
+		} else if row >= files_start && self.scroll + row - files_start < self.files.len() {
+			self.editors.len() + self.scroll + row - files_start
+		} else {
+			return Ok(());
+		};
+		let double = self
+			.last_click
+			.is_some_and(|(time, last)| last == index && time.elapsed() < DOUBLE_CLICK);
+		self.selected = index;
+		if double {
+			self.last_click = None;
+			self.enter()
+		} else {
+			self.last_click = Some((Instant::now(), index));
+			Ok(())
+		}
+	}
+
+	/// Handles typing while the filter is active, returning false for keys that should work as usual
+	fn filter_input(&mut self, event: &KeyEvent) -> bool {
+		let Some(filter) = &mut self.filter else {
+			return false;
+		};
+		let typing = (event.modifiers - KeyModifiers::SHIFT).is_empty();
+		match event.code {
+			KeyCode::Char(ch) if typing => filter.push(ch),
+			KeyCode::Backspace => _ = filter.pop(),
+			KeyCode::Esc => {
+				self.filter = None;
+				self.get_files();
+				let selected = self.filter_return.take();
+				if !selected.is_some_and(|file| self.select_file(&file)) {
+					self.selected = self.editors.len();
+				}
+				self.update_scroll();
+				return true;
+			}
+			_ => return false,
 		}
+		self.get_files();
+		// the best match is right below `..`
+		self.selected = self.editors.len() + 1.min(self.files.len() - 1);
+		self.scroll = 0;
+		true
 	}
 
-	fn message(&mut self, text: String) {
-		self.message = Some(text);
+	/// Path of the selected directory entry, if an entry rather than an editor is selected
+	fn selected_file(&self) -> Option<PathBuf> {
+		let index = self.selected.checked_sub(self.editors.len())?;
+		self.files.get(index).cloned()
+	}
+
+	/// Selects the entry with this path, returning false if it is not listed
+	fn select_file(&mut self, path: &Path) -> bool {
+		match self.files.iter().position(|f| f == path) {
+			Some(index) => {
+				self.selected = self.editors.len() + index;
+				true
+			}
+			None => false,
+		}
 	}
 
 	fn nav_up(&mut self) {
@@ -172,105 +704,704 @@ impl Navigator {
 		self.update_scroll();
 	}
 
+	/// Moves the selection by the number of listed rows, wrapping around only when already at the end
+	fn nav_page(&mut self, down: bool) {
+		let last = self.editors.len() + self.files.len() - 1;
+		let rows = self.list_rows().max(1);
+		self.selected = match (down, self.selected) {
+			(true, selected) if selected == last => 0,
+			(true, selected) => (selected + rows).min(last),
+			(false, 0) => last,
+			(false, selected) => selected.saturating_sub(rows),
+		};
+		self.update_scroll();
+	}
+
+	/// Number of directory entries that fit below the editors and the header
+	fn list_rows(&self) -> usize {
+		(screen::size().1 as usize).saturating_sub(self.editors.len() + 4)
+	}
+
 	fn update_scroll(&mut self) {
-		let height = terminal::size().unwrap().1 as usize - self.editors.len() - 5;
-		let selected_file = self.selected.saturating_sub(self.editors.len());
-		self.scroll = self
-			.scroll
-			.clamp(selected_file.saturating_sub(height), selected_file);
+		let height = self.list_rows().saturating_sub(1);
+		if let Some(selected_file) = self.selected.checked_sub(self.editors.len()) {
+			self.scroll = self
+				.scroll
+				.clamp(selected_file.saturating_sub(height), selected_file);
+		}
 	}
 
-	fn enter(&mut self) {
+	fn enter(&mut self) -> io::Result<()> {
 		if self.selected < self.editors.len() {
-			self.open_selected();
-			return;
+			return self.open_selected();
 		}
 
 		let i = self.selected - self.editors.len();
 		// top entry is hardcoded to be ../
 		if i == 0 {
-			if let Some(parent) = self.path.parent() {
-				self.set_path(self.path.join(parent));
-			}
-			return;
+			self.go_to_parent();
+			return Ok(());
 		}
 
 		let path = &self.files[i];
 		if path.is_dir() {
 			self.set_path(self.path.join(path));
-			return;
+			return Ok(());
 		}
 		if path.is_file() {
-			let path = path.canonicalize().unwrap();
-			let mut selected = self.editors.len();
-			for (i, editor) in self.editors.iter().enumerate() {
-				if editor.path() == Some(&path) {
-					selected = i;
-					break;
+			let path = match path.canonicalize() {
+				Ok(path) => path,
+				Err(err) => {
+					self.status.error(format!("Could not open file: {err}"));
+					return Ok(());
 				}
+			};
+			self.open_path(path, None)?;
+			// keep the opened file selected when returning, its index shifts if an editor was added
+			self.selected = self.editors.len() + i;
+			self.update_scroll();
+		}
+		Ok(())
+	}
+
+	/// Ancestors of the current directory from the root down, with the name shown for each.
+	/// The home directory is collapsed into `~`, and the root has an empty name.
+	fn breadcrumbs(&self) -> Vec<(String, PathBuf)> {
+		let home = env::var_os("HOME").map(PathBuf::from);
+		let mut crumbs = Vec::new();
+		for ancestor in self.path.ancestors() {
+			if Some(ancestor) == home.as_deref() {
+				crumbs.push(("~".into(), ancestor.to_path_buf()));
+				break;
 			}
-			// no editor exists with this path
-			if selected == self.editors.len() {
-				match Editor::open_file(path) {
-					Ok(editor) => self.editors.push(editor),
-					Err(err) => {
-						self.message(format!("Could not open file: {err}"));
-						return;
+			let name = ancestor
+				.file_name()
+				.map_or(String::new(), |name| name.to_string_lossy().into_owned());
+			crumbs.push((name, ancestor.to_path_buf()));
+		}
+		crumbs.reverse();
+		crumbs
+	}
+
+	fn jump_to_breadcrumb(&mut self, index: usize) {
+		if let Some((_, path)) = self.breadcrumbs().into_iter().nth(index) {
+			if path != self.path {
+				self.set_path(path);
+			}
+		}
+	}
+
+	fn go_to_parent(&mut self) {
+		if let Some(parent) = self.path.parent() {
+			self.set_path(self.path.join(parent));
+		}
+	}
+
+	/// Open a file in an editor, reusing an existing editor for that path if there is one
+	fn open_path(&mut self, path: PathBuf, position: Option<Position>) -> io::Result<()> {
+		let mut selected = self.editors.len();
+		for (i, editor) in self.editors.iter().enumerate() {
+			// untitled buffers have no path and never match
+			if editor.path().is_some_and(|p| *p == path) {
+				selected = i;
+				break;
+			}
+		}
+		// no editor exists with this path
+		if selected == self.editors.len() {
+			match Editor::open_file(path.clone()) {
+				Ok(mut editor) => {
+					if self.read_only_all {
+						editor.set_read_only(true);
+					}
+					self.editors.push(editor);
+				}
+				// e.g. a symlink to a directory in the recent files list
+				Err(OpenError::IsDirectory) => {
+					self.set_path(path);
+					return Ok(());
+				}
+				Err(err @ OpenError::InvalidUtf8(_)) => {
+					let prompt = format!(
+						"{}. Open read-only with replacement characters?",
+						open_error_message(&path, &err)
+					);
+					if !ask_yes_no(&prompt, false)? {
+						return Ok(());
+					}
+					match Editor::open_lossy(path.clone()) {
+						Ok(editor) => self.editors.push(editor),
+						Err(err) => {
+							self.status.error(open_error_message(&path, &err));
+							return Ok(());
+						}
+					}
+				}
+				Err(err) => {
+					self.status.error(open_error_message(&path, &err));
+					return Ok(());
+				}
+			}
+		}
+		self.recent.add(path);
+		self.selected = selected;
+		if let Some(position) = position {
+			self.editors[selected].go_to_position(position);
+		}
+		self.open_selected()
+	}
+
+	fn recent_files(&mut self) -> io::Result<()> {
+		let mut out = screen::out();
+		let mut selected = 0;
+		let mut scroll = 0;
+		loop {
+			let paths = self.recent.paths();
+			if paths.is_empty() {
+				self.status.info("No recent files");
+				return Ok(());
+			}
+			let last = paths.len() - 1;
+			selected = selected.min(last);
+			// the first row is the title
+			let rows = (screen::size().1 as usize).saturating_sub(1).max(1);
+			scroll = scroll.clamp((selected + 1).saturating_sub(rows), selected);
+
+			screen::clear()?;
+			queue!(out, cursor::Hide)?;
+			write!(out, "Recent files:")?;
+			for (row, index) in (scroll..paths.len()).take(rows).enumerate() {
+				let path = &paths[index];
+				if index == selected {
+					self.config.theme.selection.queue(&mut out)?;
+				} else if !path.exists() {
+					self.config.theme.dimmed.queue(&mut out)?;
+				}
+				queue!(out, screen::move_to(1, row as u16 + 1))?;
+				write!(out, "{}", path.display())?;
+				Style::reset(&mut out)?;
+			}
+			out.flush()?;
+
+			let Event::Key(event) = screen::read_event()? else {
+				continue;
+			};
+			let Some(command) = keymap::lookup(Context::RecentFiles, &event) else {
+				continue;
+			};
+			match command {
+				Command::Up => selected = selected.checked_sub(1).unwrap_or(last),
+				Command::Down => selected = if selected == last { 0 } else { selected + 1 },
+				Command::PageUp => selected = selected.saturating_sub(rows),
+				Command::PageDown => selected = (selected + rows).min(last),
+				Command::Home => selected = 0,
+				Command::End => selected = last,
+				Command::Accept => {
+					let path = paths[selected].clone();
+					if path.is_file() {
+						return self.open_path(path, None);
 					}
+					self.status
+						.error(format!("'{}' no longer exists", path.display()));
+					return Ok(());
 				}
+				Command::Delete => self.recent.remove(selected),
+				Command::Cancel => return Ok(()),
+				Command::Help => keymap::show_help(&self.config.theme)?,
+				_ => (),
 			}
-			self.selected = selected;
-			self.open_selected();
 		}
 	}
 
 	fn set_path(&mut self, new_path: PathBuf) {
+		// fail here rather than show an empty listing after moving
+		if let Err(err) = fs::read_dir(&new_path) {
+			self.status
+				.error(format!("Could not open directory: {err}"));
+			return;
+		}
 		match env::set_current_dir(&new_path) {
 			Ok(()) => {
 				self.path = new_path;
+				self.filter = None;
 				self.selected = self.editors.len();
 				self.scroll = 0;
+				self.get_files();
 			}
-			Err(err) => self.message(format!("Could not navigate to directory: {err}")),
+			Err(err) => self
+				.status
+				.error(format!("Could not navigate to directory: {err}")),
 		}
 	}
 
-	fn open_selected(&mut self) {
-		if self.selected < self.editors.len() {
-			self.scroll = 0;
-			self.editors[self.selected].enter(&mut self.config);
+	/// Asks for a path relative to the current directory, then navigates to it or opens it
+	fn go_to_path(&mut self) -> io::Result<()> {
+		let Some(input) = read_path("Path: ", Some(&mut self.config.path_history))? else {
+			return Ok(());
+		};
+		if input.is_empty() {
+			return Ok(());
+		}
+		let (target, position) = split_position(&self.path.join(&input));
+		match target.canonicalize() {
+			Ok(path) if path.is_dir() => self.set_path(path),
+			Ok(path) => self.open_path(path, position)?,
+			Err(err) => self
+				.status
+				.error(format!("Could not go to '{input}': {err}")),
+		}
+		Ok(())
+	}
+
+	/// Enters the selected editor, and the ones switched to from there
+	fn open_selected(&mut self) -> io::Result<()> {
+		while self.selected < self.editors.len() {
+			let count = self.editors.len();
+			let trim = self.config.trim_trailing_whitespace;
+			let leave = self.editors[self.selected].enter(&mut self.config, &self.clipboard)?;
+			if self.config.trim_trailing_whitespace != trim {
+				for editor in &mut self.editors {
+					editor.follow_trim_setting(&self.config);
+				}
+			}
+			match leave {
+				Leave::Navigator => break,
+				Leave::Step(step) => {
+					self.selected =
+						(self.selected as isize + step).rem_euclid(count as isize) as usize;
+				}
+				Leave::To(index) if index < count => self.selected = index,
+				Leave::To(_) => (),
+			}
 		}
+		// files may have been saved
+		self.get_files();
+		Ok(())
 	}
 
-	fn new_editor(&mut self) {
+	fn new_editor(&mut self) -> io::Result<()> {
 		self.selected = self.editors.len();
 		self.editors.push(Editor::new(None));
-		self.open_selected();
+		self.update_scroll();
+		self.open_selected()
+	}
+
+	/// Swaps the selected editor with the one below or above it, keeping it selected
+	fn move_editor(&mut self, down: bool) {
+		let other = if down {
+			self.selected + 1
+		} else {
+			self.selected.wrapping_sub(1)
+		};
+		if self.selected < self.editors.len() && other < self.editors.len() {
+			self.editors.swap(self.selected, other);
+			self.selected = other;
+		}
+	}
+
+	/// Removes the selected editor, asking first if it has unsaved changes
+	fn close_editor(&mut self) -> io::Result<()> {
+		let editor = &self.editors[self.selected];
+		if editor.is_unsaved() {
+			let name = editor.title();
+			let prompt = format!(
+				"{} has unsaved changes, close anyway?",
+				name.trim_start_matches(['*', ' '])
+			);
+			if !ask_yes_no(&prompt, false)? {
+				return Ok(());
+			}
+		}
+		self.editors.remove(self.selected);
+		// stay in the editor list unless it is now empty
+		if self.selected == self.editors.len() && self.selected > 0 {
+			self.selected -= 1;
+		}
+		self.update_scroll();
+		Ok(())
+	}
+
+	/// Copy the absolute path (or just the file name) of the selected entry to the clipboard
+	fn copy_path(&mut self, name_only: bool) {
+		let path = if self.selected < self.editors.len() {
+			self.editors[self.selected].path().cloned()
+		} else {
+			let path = &self.files[self.selected - self.editors.len()];
+			self.path.join(path).canonicalize().ok()
+		};
+		let Some(path) = path else {
+			self.status.info("Nothing to copy");
+			return;
+		};
+		let text = match path.file_name() {
+			Some(name) if name_only => name.to_string_lossy().into_owned(),
+			_ => path.to_string_lossy().into_owned(),
+		};
+		self.status.info(format!("Copied '{text}'"));
+		self.clipboard.set(text, false);
+		if let Some(warning) = self.clipboard.take_warning() {
+			self.status.error(warning);
+		}
+	}
+
+	fn save_settings(&mut self) {
+		match self.config.save() {
+			Ok(()) => self.status.info("Saved settings"),
+			Err(err) => self.status.error(format!("Could not save settings: {err}")),
+		}
+	}
+
+	fn cycle_theme(&mut self) {
+		let names = Theme::NAMES;
+		let current = names
+			.iter()
+			.position(|name| *name == self.config.theme_name);
+		let next = names[current.map_or(0, |i| (i + 1) % names.len())];
+		self.config.select_theme(next);
+		match self.config.settings_changed() {
+			Ok(()) => self.status.info(format!("Theme: {next}")),
+			Err(err) => self.status.error(format!("Could not save settings: {err}")),
+		}
 	}
 
 	fn get_files(&mut self) {
 		self.files.clear();
+		self.details_cache.clear();
 		self.files.push(PathBuf::from(".."));
-		for file in fs::read_dir(&self.path).unwrap().flatten() {
-			self.files.push(file.path());
+		self.list_error = None;
+		self.hidden_count = 0;
+		self.filtered_count = 0;
+		let filter = self.filter.as_deref().unwrap_or_default().to_lowercase();
+		match fs::read_dir(&self.path) {
+			Ok(entries) => {
+				for file in entries.flatten() {
+					let name = file.file_name().to_string_lossy().to_lowercase();
+					if !self.show_hidden && name.starts_with('.') {
+						self.hidden_count += 1;
+					} else if !name.contains(&filter) {
+						self.filtered_count += 1;
+					} else {
+						self.files.push(file.path());
+					}
+				}
+			}
+			Err(err) if err.kind() == ErrorKind::NotFound => {
+				// deleted while it was open, go to what is left of the path
+				let existing = self.path.ancestors().skip(1).find(|p| p.is_dir());
+				if let Some(existing) = existing.map(Path::to_path_buf) {
+					self.status.error(format!(
+						"'{}' no longer exists, moved up to '{}'",
+						self.path.display(),
+						existing.display()
+					));
+					self.set_path(existing.clone());
+					if self.path == existing {
+						// listed by `set_path`
+						return;
+					}
+				}
+				self.list_error = Some(err.to_string());
+			}
+			Err(err) => self.list_error = Some(err.to_string()),
 		}
-		self.files[1..].sort_unstable_by(|path, other| {
-			let by_type = path.is_file().cmp(&other.is_file());
-			let by_name = path.cmp(other);
-			by_type.then(by_name)
+		// directories first, `..` stays at the top
+		let sort = self.sort;
+		self.files[1..].sort_by_cached_key(|path| {
+			let metadata = fs::metadata(path).ok();
+			let is_file = !metadata.as_ref().is_some_and(|m| m.is_dir());
+			let (modified, size) = match (sort, &metadata) {
+				(SortMode::Modified, Some(m)) => (m.modified().ok(), 0),
+				(SortMode::Size, Some(m)) => (None, m.len()),
+				_ => (None, 0),
+			};
+			let name = path.file_name().unwrap_or_default().to_string_lossy();
+			let by_name = (name.to_lowercase(), name.into_owned());
+			(is_file, Reverse(modified), Reverse(size), by_name)
 		});
 	}
 
+	/// Deletes the selected file or directory after asking, refusing ones that are open in an editor
+	fn delete_selected(&mut self) -> io::Result<()> {
+		// the `..` entry and editors can't be deleted
+		let Some(index) = self
+			.selected
+			.checked_sub(self.editors.len())
+			.filter(|&i| i > 0)
+		else {
+			return Ok(());
+		};
+		let path = self.files[index].clone();
+		let name = path.file_name().unwrap_or_default().to_string_lossy();
+		let full_path = path.canonicalize().unwrap_or_else(|_| path.clone());
+		if self
+			.editors
+			.iter()
+			.any(|editor| editor.path().is_some_and(|p| p.starts_with(&full_path)))
+		{
+			self.status
+				.error(format!("'{name}' is open in an editor, close it first"));
+			return Ok(());
+		}
+		if !ask_yes_no(&format!("Delete '{name}'?"), false)? {
+			return Ok(());
+		}
+		// a symlink is removed itself, never what it points to
+		let is_dir = fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
+		let result = if is_dir {
+			match fs::remove_dir(&path) {
+				Err(err) if err.kind() == ErrorKind::DirectoryNotEmpty => {
+					let prompt =
+						format!("'{name}' is not empty. Delete it and everything inside it?");
+					if !ask_yes_no(&prompt, false)? {
+						return Ok(());
+					}
+					fs::remove_dir_all(&path)
+				}
+				result => result,
+			}
+		} else {
+			fs::remove_file(&path)
+		};
+		match result {
+			Ok(()) => self.status.info(format!("Deleted '{name}'")),
+			Err(err) => self
+				.status
+				.error(format!("Could not delete '{name}': {err}")),
+		}
+		self.get_files();
+		// the entry that took its place, or the one before if it was last
+		self.selected = self.selected.min(self.editors.len() + self.files.len() - 1);
+		self.update_scroll();
+		Ok(())
+	}
+
+	/// Renames the selected entry, possibly into another directory, and updates editors that have it open
+	fn rename_selected(&mut self) -> io::Result<()> {
+		let Some(index) = self
+			.selected
+			.checked_sub(self.editors.len())
+			.filter(|&i| i > 0)
+		else {
+			return Ok(());
+		};
+		let path = self.files[index].clone();
+		let name = path.file_name().unwrap_or_default().to_string_lossy();
+		let Some(new_name) = edit_line("Rename to: ", &name)? else {
+			return Ok(());
+		};
+		if new_name.is_empty() || new_name == name {
+			return Ok(());
+		}
+		let target = self.path.join(&new_name);
+		if fs::symlink_metadata(&target).is_ok()
+			&& !ask_yes_no(&format!("'{new_name}' already exists, overwrite?"), false)?
+		{
+			return Ok(());
+		}
+		let old_full = path.canonicalize().unwrap_or_else(|_| path.clone());
+		if let Err(err) = fs::rename(&path, &target) {
+			self.status
+				.error(format!("Could not rename '{name}': {err}"));
+			return Ok(());
+		}
+		let new_full = target.canonicalize().unwrap_or_else(|_| target.clone());
+		for editor in &mut self.editors {
+			let moved = editor
+				.path()
+				.and_then(|p| p.strip_prefix(&old_full).ok())
+				// joining an empty rest would add a trailing slash
+				.map(|rest| new_full.join(rest).components().collect::<PathBuf>());
+			if let Some(moved) = moved {
+				editor.set_path(moved, &self.config);
+			}
+		}
+		self.status
+			.info(format!("Renamed '{name}' to '{new_name}'"));
+		self.get_files();
+		if let Some(i) = self.files.iter().position(|f| *f == target) {
+			self.selected = self.editors.len() + i;
+		} else {
+			// moved to another directory
+			self.selected = self.selected.min(self.editors.len() + self.files.len() - 1);
+		}
+		self.update_scroll();
+		Ok(())
+	}
+
 	fn any_unsaved(&self) -> bool {
 		self.editors.iter().any(Editor::is_unsaved)
 	}
 
-	fn quit(&self) {
-		if self.any_unsaved() && !ask_yes_no("Unsaved changes, quit anyway?", false) {
-			return;
+	fn quit(&self) -> io::Result<()> {
+		if self.any_unsaved() && !ask_yes_no("Unsaved changes, quit anyway?", false)? {
+			return Ok(());
+		}
+		if let Err(err) = screen::leave() {
+			eprintln!("lili: could not restore the terminal: {err}");
 		}
-		disable_raw_mode().unwrap();
-		execute!(stdout(), LeaveAlternateScreen, cursor::Show).unwrap();
 		exit(0);
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::PoisonError;
+
+	fn press(code: KeyCode, modifiers: KeyModifiers) -> Event {
+		Event::Key(KeyEvent::new(code, modifiers))
+	}
+
+	fn typed(text: &str) -> Vec<Event> {
+		text.chars()
+			.map(|ch| press(KeyCode::Char(ch), KeyModifiers::NONE))
+			.collect()
+	}
+
+	/// Empty directory under the system temp directory, removed again by the caller
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = env::temp_dir().join(format!("lili-test-{}-{name}", std::process::id()));
+		_ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir.canonicalize().unwrap()
+	}
+
+	/// Runs lili on `paths` until the events run out, returning everything drawn
+	fn run_scripted(paths: Vec<PathBuf>, events: Vec<Event>) -> String {
+		// the config directory and the current directory are also shared by the whole process
+		let _lock = screen::TEST_LOCK
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner);
+		let home = temp_dir("config");
+		env::set_var("XDG_CONFIG_HOME", &home);
+		env::set_current_dir(&home).unwrap();
+		let backend = screen::Scripted::new(events, (60, 20));
+		let output = backend.output();
+		screen::set_backend(Box::new(backend));
+		let args = Args {
+			paths,
+			read_only: false,
+			read_only_all: false,
+			print: false,
+			inline: false,
+			log: None,
+			batch: None,
+		};
+		let err = Navigator::new(args).run().unwrap_err();
+		assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+		// the navigator may have moved into a directory that is about to be removed
+		env::set_current_dir(env::temp_dir()).unwrap();
+		_ = fs::remove_dir_all(&home);
+		let output = output.lock().unwrap_or_else(PoisonError::into_inner);
+		String::from_utf8_lossy(&output).into_owned()
+	}
+
+	#[test]
+	fn open_type_and_save() {
+		let dir = temp_dir("save");
+		let file = dir.join("notes.txt");
+		fs::write(&file, "world\n").unwrap();
+		let mut events = typed("hello ");
+		events.push(press(KeyCode::Char('s'), KeyModifiers::CONTROL));
+		let output = run_scripted(vec![file.clone()], events);
+		assert_eq!(fs::read_to_string(&file).unwrap(), "hello world\n");
+		assert!(output.contains("Saved file"));
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn navigate_into_directory_and_open_file() {
+		let dir = temp_dir("navigate");
+		fs::create_dir(dir.join("sub")).unwrap();
+		fs::write(dir.join("sub").join("inner.txt"), "one\n").unwrap();
+		fs::write(dir.join("outer.txt"), "").unwrap();
+		// `..` is listed first, then directories before files
+		let mut events = vec![
+			press(KeyCode::Down, KeyModifiers::NONE),
+			press(KeyCode::Enter, KeyModifiers::NONE),
+			press(KeyCode::Down, KeyModifiers::NONE),
+			press(KeyCode::Enter, KeyModifiers::NONE),
+		];
+		events.extend(typed("zero "));
+		events.push(press(KeyCode::Char('s'), KeyModifiers::CONTROL));
+		let output = run_scripted(vec![dir.clone()], events);
+		let inner = fs::read_to_string(dir.join("sub").join("inner.txt")).unwrap();
+		assert_eq!(inner, "zero one\n");
+		assert!(output.contains("outer.txt"));
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn file_opened_by_relative_path_cannot_be_deleted() {
+		let dir = temp_dir("relative");
+		fs::write(dir.join("notes.txt"), "").unwrap();
+		// relative to the config directory the run starts in, a sibling of `dir`
+		let relative = Path::new("..")
+			.join(dir.file_name().unwrap())
+			.join("notes.txt");
+		let events = vec![
+			press(KeyCode::Esc, KeyModifiers::NONE),
+			press(KeyCode::Down, KeyModifiers::NONE),
+			press(KeyCode::Down, KeyModifiers::NONE),
+			press(KeyCode::Delete, KeyModifiers::NONE),
+		];
+		let output = run_scripted(vec![relative, dir.clone()], events);
+		assert!(output.contains("is open in an editor"));
+		assert!(dir.join("notes.txt").exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn renaming_follows_a_file_opened_by_relative_path() {
+		let dir = temp_dir("rename");
+		fs::write(dir.join("old.txt"), "text\n").unwrap();
+		let relative = Path::new("..")
+			.join(dir.file_name().unwrap())
+			.join("old.txt");
+		let mut events = vec![
+			press(KeyCode::Esc, KeyModifiers::NONE),
+			press(KeyCode::Down, KeyModifiers::NONE),
+			press(KeyCode::Down, KeyModifiers::NONE),
+			press(KeyCode::Char('r'), KeyModifiers::NONE),
+		];
+		// the prompt starts with the old name typed
+		events.extend(vec![
+			press(KeyCode::Backspace, KeyModifiers::NONE);
+			"old.txt".len()
+		]);
+		events.extend(typed("new.txt"));
+		events.extend([
+			press(KeyCode::Enter, KeyModifiers::NONE),
+			press(KeyCode::Home, KeyModifiers::NONE),
+			press(KeyCode::Enter, KeyModifiers::NONE),
+		]);
+		events.extend(typed("more "));
+		events.push(press(KeyCode::Char('s'), KeyModifiers::CONTROL));
+		run_scripted(vec![relative, dir.clone()], events);
+		assert_eq!(
+			fs::read_to_string(dir.join("new.txt")).unwrap(),
+			"more text\n"
+		);
+		assert!(!dir.join("old.txt").exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn open_oldest_recent_file() {
+		let dir = temp_dir("recent");
+		fs::write(dir.join("a.txt"), "").unwrap();
+		fs::write(dir.join("b.txt"), "").unwrap();
+		let paths = vec![dir.join("a.txt"), dir.join("b.txt"), dir.clone()];
+		let mut events = vec![
+			press(KeyCode::Char('o'), KeyModifiers::NONE),
+			press(KeyCode::End, KeyModifiers::NONE),
+			press(KeyCode::Enter, KeyModifiers::NONE),
+		];
+		events.extend(typed("first"));
+		events.push(press(KeyCode::Char('s'), KeyModifiers::CONTROL));
+		let output = run_scripted(paths, events);
+		assert!(output.contains("Recent files:"));
+		assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "first\n");
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
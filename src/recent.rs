@@ -0,0 +1,63 @@
+use std::{fs, path::PathBuf};
+
+use crate::config::config_dir;
+
+const MAX_ENTRIES: usize = 32;
+
+/// Most recently opened files, newest first, persisted between sessions
+pub struct RecentFiles {
+	paths: Vec<PathBuf>,
+}
+
+impl RecentFiles {
+	pub fn load() -> Self {
+		let paths = storage_path()
+			.and_then(|file| fs::read_to_string(file).ok())
+			.map(|text| {
+				text.lines()
+					.filter(|line| !line.is_empty())
+					.map(PathBuf::from)
+					.collect()
+			})
+			.unwrap_or_default();
+		Self { paths }
+	}
+
+	pub fn paths(&self) -> &[PathBuf] {
+		&self.paths
+	}
+
+	pub fn add(&mut self, path: PathBuf) {
+		self.paths.retain(|p| p != &path);
+		self.paths.insert(0, path);
+		self.paths.truncate(MAX_ENTRIES);
+		self.save();
+	}
+
+	pub fn remove(&mut self, index: usize) {
+		if index < self.paths.len() {
+			self.paths.remove(index);
+			self.save();
+		}
+	}
+
+	/// Failing to store the list is not worth interrupting the user over, so errors are ignored
+	fn save(&self) {
+		let Some(file) = storage_path() else {
+			return;
+		};
+		if let Some(dir) = file.parent() {
+			_ = fs::create_dir_all(dir);
+		}
+		let text: String = self
+			.paths
+			.iter()
+			.map(|path| format!("{}\n", path.to_string_lossy()))
+			.collect();
+		_ = fs::write(file, text);
+	}
+}
+
+fn storage_path() -> Option<PathBuf> {
+	config_dir().map(|dir| dir.join("recent_files"))
+}
@@ -0,0 +1,341 @@
+use crossterm::{
+	cursor::{self, MoveTo},
+	event::{self, DisableMouseCapture, EnableMouseCapture, Event},
+	execute, queue,
+	terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+	Command,
+};
+#[cfg(test)]
+use std::{collections::VecDeque, sync::Arc};
+use std::{
+	fmt::Display,
+	io::{self, stdout, ErrorKind, Write},
+	process::exit,
+	sync::{
+		atomic::{AtomicBool, AtomicU16, Ordering},
+		Mutex, PoisonError,
+	},
+	time::Duration,
+};
+
+use crate::log::log;
+
+// lili draws either on the whole alternate screen, or in inline mode on the rows from
+// the shell's cursor position down to the bottom of the terminal. Drawing code uses
+// `size`, `move_to` and `clear` from here instead of the crossterm equivalents.
+static INLINE: AtomicBool = AtomicBool::new(false);
+/// First terminal row of the drawing area
+static ORIGIN: AtomicU16 = AtomicU16::new(0);
+static MOUSE_CAPTURED: AtomicBool = AtomicBool::new(false);
+/// Between `enter` and `leave`, so a panic knows whether the terminal needs restoring
+static ENTERED: AtomicBool = AtomicBool::new(false);
+/// Something was drawn over the text area since the last `take_overlay`
+static OVERLAY: AtomicBool = AtomicBool::new(false);
+/// Replaces the terminal when set, see `set_backend`
+static BACKEND: Mutex<Option<Box<dyn Backend>>> = Mutex::new(None);
+/// Held by tests that set a backend, since it is shared by the whole process
+#[cfg(test)]
+pub static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Where key events come from and drawn output goes to
+pub trait Backend: Send {
+	/// Prepares the drawing area, `inline` as described for `enter`
+	fn enter(&mut self, inline: bool) -> io::Result<()>;
+	fn leave(&mut self) -> io::Result<()>;
+	/// Blocks until the next event
+	fn read_event(&mut self) -> io::Result<Event>;
+	/// Whether an event arrives within `timeout`
+	fn poll_event(&mut self, timeout: Duration) -> io::Result<bool>;
+	/// Columns and rows of the whole screen
+	fn size(&self) -> io::Result<(u16, u16)>;
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
+	fn flush(&mut self) -> io::Result<()>;
+}
+
+/// The real terminal through crossterm, used unless another backend is set
+struct Terminal;
+
+impl Backend for Terminal {
+	fn enter(&mut self, inline: bool) -> io::Result<()> {
+		terminal::enable_raw_mode()?;
+		if !inline {
+			return execute!(stdout(), EnterAlternateScreen, Clear(ClearType::All));
+		}
+		let (_, row) = cursor::position()?;
+		let (_, height) = terminal::size()?;
+		let rows = (height - row).max(height / 2);
+		let origin = height - rows;
+		if origin < row {
+			// newlines on the bottom row push the old contents into the scrollback
+			queue!(stdout(), MoveTo(0, height - 1))?;
+			write!(stdout(), "{}", "\n".repeat((row - origin) as usize))?;
+		}
+		ORIGIN.store(origin, Ordering::Relaxed);
+		queue!(
+			stdout(),
+			MoveTo(0, origin),
+			Clear(ClearType::FromCursorDown)
+		)?;
+		stdout().flush()
+	}
+
+	fn leave(&mut self) -> io::Result<()> {
+		let screen = if INLINE.load(Ordering::Relaxed) {
+			let (_, height) = terminal::size().unwrap_or((80, 24));
+			queue!(
+				stdout(),
+				MoveTo(0, height - 1),
+				Clear(ClearType::CurrentLine)
+			)
+		} else {
+			queue!(stdout(), LeaveAlternateScreen)
+		};
+		let cursor = execute!(stdout(), cursor::Show);
+		let raw_mode = terminal::disable_raw_mode();
+		screen.and(cursor).and(raw_mode)
+	}
+
+	fn read_event(&mut self) -> io::Result<Event> {
+		event::read()
+	}
+
+	fn poll_event(&mut self, timeout: Duration) -> io::Result<bool> {
+		event::poll(timeout)
+	}
+
+	fn size(&self) -> io::Result<(u16, u16)> {
+		terminal::size()
+	}
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		stdout().write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		stdout().flush()
+	}
+}
+
+/// Plays back a fixed list of events and records everything drawn, for running the UI without a terminal.
+/// Reading past the last event fails with `ErrorKind::UnexpectedEof`.
+#[cfg(test)]
+pub struct Scripted {
+	events: VecDeque<Event>,
+	size: (u16, u16),
+	output: Arc<Mutex<Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl Scripted {
+	pub fn new(events: impl IntoIterator<Item = Event>, size: (u16, u16)) -> Self {
+		Self {
+			events: events.into_iter().collect(),
+			size,
+			output: Arc::default(),
+		}
+	}
+
+	/// Shared handle to the recorded output, still readable after the backend is handed over
+	pub fn output(&self) -> Arc<Mutex<Vec<u8>>> {
+		self.output.clone()
+	}
+}
+
+#[cfg(test)]
+impl Backend for Scripted {
+	fn enter(&mut self, _inline: bool) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn leave(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn read_event(&mut self) -> io::Result<Event> {
+		self.events
+			.pop_front()
+			.ok_or_else(|| io::Error::new(ErrorKind::UnexpectedEof, "no more scripted events"))
+	}
+
+	fn poll_event(&mut self, _timeout: Duration) -> io::Result<bool> {
+		// reading after the last event fails, which ends the run
+		Ok(true)
+	}
+
+	fn size(&self) -> io::Result<(u16, u16)> {
+		Ok(self.size)
+	}
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let mut output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+		output.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+/// Uses `backend` instead of the terminal from now on
+#[cfg(test)]
+pub fn set_backend(backend: Box<dyn Backend>) {
+	*BACKEND.lock().unwrap_or_else(PoisonError::into_inner) = Some(backend);
+}
+
+fn with_backend<T>(f: impl FnOnce(&mut dyn Backend) -> T) -> T {
+	let mut backend = BACKEND.lock().unwrap_or_else(PoisonError::into_inner);
+	match backend.as_mut() {
+		Some(backend) => f(backend.as_mut()),
+		None => f(&mut Terminal),
+	}
+}
+
+/// Writes to the current backend; use this instead of `stdout()` for anything drawn
+pub struct Output;
+
+impl Write for Output {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		with_backend(|backend| backend.write(buf))
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		with_backend(|backend| backend.flush())
+	}
+}
+
+pub fn out() -> Output {
+	Output
+}
+
+/// Waits for the next key, mouse or resize event
+pub fn read_event() -> io::Result<Event> {
+	with_backend(|backend| backend.read_event())
+}
+
+/// Waits up to `timeout` for an event, returning whether `read_event` has one ready
+pub fn poll_event(timeout: Duration) -> io::Result<bool> {
+	with_backend(|backend| backend.poll_event(timeout))
+}
+
+/// Switches to raw mode and sets up the drawing area.
+/// In inline mode the terminal is scrolled if needed so that at least half of it is available.
+pub fn enter(inline: bool) -> io::Result<()> {
+	INLINE.store(inline, Ordering::Relaxed);
+	ENTERED.store(true, Ordering::Relaxed);
+	with_backend(|backend| backend.enter(inline))
+}
+
+/// Restores the terminal. In inline mode the last drawn contents stay visible
+/// and only the bottom row is cleared for the shell prompt.
+/// Every step is attempted even if an earlier one fails, and the first error is returned.
+pub fn leave() -> io::Result<()> {
+	ENTERED.store(false, Ordering::Relaxed);
+	with_backend(restore)
+}
+
+/// `leave` for the panic hook, if the terminal was entered. The backend is not waited for since
+/// a panic while drawing happens with it locked further up the same thread.
+pub fn leave_after_panic() {
+	if !ENTERED.swap(false, Ordering::Relaxed) {
+		return;
+	}
+	_ = match BACKEND.try_lock() {
+		Ok(mut backend) => match backend.as_mut() {
+			Some(backend) => restore(backend.as_mut()),
+			None => restore(&mut Terminal),
+		},
+		Err(_) => restore(&mut Terminal),
+	};
+}
+
+fn restore(backend: &mut dyn Backend) -> io::Result<()> {
+	let mut mouse = Ok(());
+	if MOUSE_CAPTURED.swap(false, Ordering::Relaxed) {
+		let mut ansi = String::new();
+		_ = DisableMouseCapture.write_ansi(&mut ansi);
+		mouse = backend.write(ansi.as_bytes()).and_then(|_| backend.flush());
+	}
+	mouse.and(backend.leave())
+}
+
+/// Mouse capture stops the terminal's own text selection from working, so it can be turned off
+pub fn set_mouse_capture(on: bool) -> io::Result<()> {
+	if on {
+		execute!(out(), EnableMouseCapture)?;
+	} else {
+		execute!(out(), DisableMouseCapture)?;
+	}
+	MOUSE_CAPTURED.store(on, Ordering::Relaxed);
+	Ok(())
+}
+
+/// Flips mouse capture, returning a message describing the result
+pub fn toggle_mouse_capture() -> String {
+	let on = !MOUSE_CAPTURED.load(Ordering::Relaxed);
+	match set_mouse_capture(on) {
+		Ok(()) if on => "Mouse capture on".into(),
+		Ok(()) => "Mouse capture off, the terminal can select text".into(),
+		Err(err) => format!("Could not change mouse capture: {err}"),
+	}
+}
+
+/// Width and height of the drawing area
+pub fn size() -> (u16, u16) {
+	// keep going with a typical size if the terminal can't tell, e.g. while a tmux session is detached
+	let (width, height) = with_backend(|backend| backend.size()).unwrap_or((80, 24));
+	let origin = ORIGIN.load(Ordering::Relaxed);
+	(width, height.saturating_sub(origin).max(1))
+}
+
+/// Converts a row from a mouse event to one relative to the top of the drawing area
+pub fn relative_row(row: u16) -> u16 {
+	row.saturating_sub(ORIGIN.load(Ordering::Relaxed))
+}
+
+/// Moves the cursor to a position relative to the top of the drawing area
+pub fn move_to(column: u16, row: u16) -> MoveTo {
+	MoveTo(column, row + ORIGIN.load(Ordering::Relaxed))
+}
+
+/// Records that a prompt or overlay drew over rows an editor may think are still showing its text
+pub fn overlay_drawn() {
+	OVERLAY.store(true, Ordering::Relaxed);
+}
+
+/// Whether anything was drawn over the text area since the last call
+pub fn take_overlay() -> bool {
+	OVERLAY.swap(false, Ordering::Relaxed)
+}
+
+/// Clears the drawing area and moves the cursor to its top left corner
+pub fn clear() -> io::Result<()> {
+	overlay_drawn();
+	queue!(out(), move_to(0, 0), Clear(ClearType::FromCursorDown))
+}
+
+/// Clears from the cursor to the end of its row, after drawing a row over the previous frame
+pub fn clear_line_end() -> io::Result<()> {
+	queue!(out(), Clear(ClearType::UntilNewLine))
+}
+
+/// Clears everything from the start of `row` down, for rows the current frame leaves empty
+pub fn clear_below(row: u16) -> io::Result<()> {
+	queue!(out(), move_to(0, row), Clear(ClearType::FromCursorDown))
+}
+
+/// Errors that are likely to go away when the operation is simply tried again
+pub fn is_transient(err: &io::Error) -> bool {
+	matches!(
+		err.kind(),
+		ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+	)
+}
+
+/// Restores the terminal as well as possible and exits with an error message
+pub fn fail(err: impl Display) -> ! {
+	log!("fatal error: {err}");
+	_ = leave();
+	eprintln!("lili: {err}");
+	exit(1);
+}
@@ -0,0 +1,120 @@
+use crossterm::{
+	queue,
+	terminal::{Clear, ClearType},
+};
+use std::io::{self, Write};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+	log::log,
+	screen,
+	theme::{Style, Theme},
+	util::truncate_end,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+	Info,
+	Error,
+}
+
+#[derive(Debug)]
+struct Message {
+	text: String,
+	severity: Severity,
+	/// Kept until the next key press instead of only for the next redraw
+	sticky: bool,
+}
+
+/// The bottom row of a view. Shows the latest message if there is one,
+/// otherwise the segments given by the view.
+#[derive(Debug, Default)]
+pub struct StatusBar {
+	message: Option<Message>,
+}
+
+impl StatusBar {
+	pub fn info(&mut self, text: impl Into<String>) {
+		self.set(text.into(), Severity::Info, false);
+	}
+
+	pub fn error(&mut self, text: impl Into<String>) {
+		self.set(text.into(), Severity::Error, false);
+	}
+
+	/// Shows a message that survives redraws until the user presses a key
+	pub fn sticky(&mut self, text: impl Into<String>, severity: Severity) {
+		self.set(text.into(), severity, true);
+	}
+
+	fn set(&mut self, text: String, severity: Severity, sticky: bool) {
+		log!("message: {text}");
+		self.message = Some(Message {
+			text,
+			severity,
+			sticky,
+		});
+	}
+
+	/// Called when a key is pressed, clearing a sticky message
+	pub fn dismiss(&mut self) {
+		self.message = None;
+	}
+
+	/// Draws the bar with `left` and `right` aligned to the edges, or the current message instead.
+	/// A message that isn't sticky is cleared once drawn.
+	pub fn draw(
+		&mut self,
+		out: &mut impl Write,
+		theme: &Theme,
+		left: &str,
+		right: &str,
+	) -> io::Result<()> {
+		let width = screen::size().0 as usize;
+		let (text, style) = match &self.message {
+			Some(message) => {
+				let style = match message.severity {
+					Severity::Info => theme.status,
+					Severity::Error => Style {
+						fg: theme.error.fg,
+						..theme.status
+					},
+				};
+				(truncate_end(&message.text, width), style)
+			}
+			None => {
+				let right_width = right.width();
+				let left = truncate_end(left, width.saturating_sub(right_width + 1));
+				let gap = width.saturating_sub(left.width() + right_width);
+				let text = if right_width < width {
+					format!("{left}{:gap$}{right}", "")
+				} else {
+					truncate_end(right, width)
+				};
+				(text, theme.status)
+			}
+		};
+		if self.message.as_ref().is_some_and(|m| !m.sticky) {
+			self.message = None;
+		}
+		begin(out)?;
+		style.queue(out)?;
+		let padding = width.saturating_sub(text.width());
+		write!(out, "{text}{:padding$}", "")?;
+		Style::reset(out)
+	}
+}
+
+/// Row of the bar, relative to the top of the drawing area
+pub fn row() -> u16 {
+	screen::size().1 - 1
+}
+
+/// Moves to the start of the bar and clears it, for prompts that temporarily take it over
+pub fn begin(out: &mut impl Write) -> io::Result<()> {
+	queue!(
+		out,
+		screen::move_to(0, row()),
+		Clear(ClearType::CurrentLine)
+	)
+}
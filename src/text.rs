@@ -0,0 +1,389 @@
+use std::{
+	borrow::Cow,
+	fmt,
+	io::{self, Write},
+	iter,
+	ops::Range,
+};
+
+/// Chunks are cut to this many bytes, moved up to the next character boundary
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Contents of a buffer, stored in chunks so that an edit only rewrites the chunk it is in
+/// instead of moving everything after it. Positions are byte offsets into the whole text.
+#[derive(Debug)]
+pub struct Text {
+	/// Never empty, and only holds an empty chunk when the whole text is empty
+	chunks: Vec<String>,
+	/// Position where each chunk starts
+	starts: Vec<usize>,
+	len: usize,
+}
+
+impl Default for Text {
+	fn default() -> Self {
+		Self::from(String::new())
+	}
+}
+
+impl From<String> for Text {
+	fn from(text: String) -> Self {
+		let mut this = Self {
+			len: text.len(),
+			chunks: split(text),
+			starts: Vec::new(),
+		};
+		this.update_starts(0);
+		this
+	}
+}
+
+impl Text {
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	/// Index of the chunk containing `pos`, the later one where two meet
+	fn chunk_at(&self, pos: usize) -> usize {
+		self.starts.partition_point(|&start| start <= pos) - 1
+	}
+
+	/// The text in `range`, only copied if it crosses chunks. Panics like slicing a `str`.
+	pub fn slice(&self, range: Range<usize>) -> Cow<'_, str> {
+		assert!(
+			range.end <= self.len,
+			"{range:?} out of bounds of {}",
+			self.len
+		);
+		let i = self.chunk_at(range.start);
+		let start = self.starts[i];
+		if range.end - start <= self.chunks[i].len() {
+			return Cow::Borrowed(&self.chunks[i][range.start - start..range.end - start]);
+		}
+		let mut text = String::with_capacity(range.len());
+		let mut pos = range.start;
+		while pos < range.end {
+			let i = self.chunk_at(pos);
+			let offset = pos - self.starts[i];
+			let end = self.chunks[i].len().min(range.end - self.starts[i]);
+			text += &self.chunks[i][offset..end];
+			pos += end - offset;
+		}
+		Cow::Owned(text)
+	}
+
+	pub fn byte(&self, pos: usize) -> Option<u8> {
+		let i = self.chunk_at(pos);
+		self.chunks[i].as_bytes().get(pos - self.starts[i]).copied()
+	}
+
+	pub fn bytes(&self) -> impl DoubleEndedIterator<Item = u8> + '_ {
+		self.chunks.iter().flat_map(|chunk| chunk.bytes())
+	}
+
+	pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+		self.chunks.iter().flat_map(|chunk| chunk.chars())
+	}
+
+	/// Range of every line, not including its `\n`
+	pub fn lines(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+		let newlines = (self.chunks.iter().zip(&self.starts))
+			.flat_map(|(chunk, &start)| chunk.match_indices('\n').map(move |(i, _)| start + i));
+		let mut line_start = 0;
+		newlines.chain(iter::once(self.len)).map(move |end| {
+			let line = line_start..end;
+			line_start = end + 1;
+			line
+		})
+	}
+
+	/// Start of the first occurrence of `pattern` lying within `range`
+	pub fn find(&self, pattern: &str, range: Range<usize>) -> Option<usize> {
+		if pattern.is_empty() {
+			return Some(range.start);
+		}
+		for i in self.chunk_at(range.start)..=self.chunk_at(range.end) {
+			let start = self.starts[i];
+			let end = start + self.chunks[i].len();
+			let (from, to) = (range.start.max(start), range.end.min(end));
+			if let Some(found) = self.chunks[i][from - start..to - start].find(pattern) {
+				return Some(from + found);
+			}
+			if let Some(found) = self.find_across(pattern, end, &range, false) {
+				return Some(found);
+			}
+		}
+		None
+	}
+
+	/// Start of the last occurrence of `pattern` lying within `range`
+	pub fn rfind(&self, pattern: &str, range: Range<usize>) -> Option<usize> {
+		if pattern.is_empty() {
+			return Some(range.end);
+		}
+		for i in (self.chunk_at(range.start)..=self.chunk_at(range.end)).rev() {
+			let start = self.starts[i];
+			let end = start + self.chunks[i].len();
+			if let Some(found) = self.find_across(pattern, end, &range, true) {
+				return Some(found);
+			}
+			let (from, to) = (range.start.max(start), range.end.min(end));
+			if let Some(found) = self.chunks[i][from - start..to - start].rfind(pattern) {
+				return Some(from + found);
+			}
+		}
+		None
+	}
+
+	/// Looks for `pattern` crossing from one chunk into the next at `boundary`,
+	/// copying only the few bytes around it
+	fn find_across(
+		&self,
+		pattern: &str,
+		boundary: usize,
+		range: &Range<usize>,
+		backward: bool,
+	) -> Option<usize> {
+		if boundary <= range.start || boundary >= range.end {
+			return None;
+		}
+		let mut start = (boundary + 1)
+			.saturating_sub(pattern.len())
+			.max(range.start);
+		while !self.is_char_boundary(start) {
+			start -= 1;
+		}
+		let mut end = (boundary + pattern.len() - 1).min(range.end);
+		while !self.is_char_boundary(end) {
+			end += 1;
+		}
+		let window = self.slice(start..end);
+		let found = if backward {
+			window.rfind(pattern)
+		} else {
+			window.find(pattern)
+		};
+		found.map(|i| start + i)
+	}
+
+	/// Starts of the occurrences of `pattern`, which must not be empty, without overlaps
+	pub fn match_indices<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = usize> + 'a {
+		let mut pos = 0;
+		iter::from_fn(move || {
+			let found = self.find(pattern, pos..self.len)?;
+			pos = found + pattern.len();
+			Some(found)
+		})
+	}
+
+	pub fn is_char_boundary(&self, pos: usize) -> bool {
+		let i = self.chunk_at(pos.min(self.len));
+		pos <= self.len && self.chunks[i].is_char_boundary(pos - self.starts[i])
+	}
+
+	/// The character starting at `pos`
+	pub fn char_at(&self, pos: usize) -> Option<char> {
+		let end = (pos + 4).min(self.len);
+		let end = (pos..=end).rev().find(|&end| self.is_char_boundary(end))?;
+		self.slice(pos..end).chars().next()
+	}
+
+	/// The character ending at `pos`
+	pub fn char_before(&self, pos: usize) -> Option<char> {
+		let start = (pos.saturating_sub(4)..pos).find(|&start| self.is_char_boundary(start))?;
+		self.slice(start..pos).chars().next_back()
+	}
+
+	/// Whether `text` occurs at `pos`
+	pub fn matches_at(&self, pos: usize, text: &str) -> bool {
+		pos + text.len() <= self.len
+			&& (text.bytes().enumerate()).all(|(i, byte)| self.byte(pos + i) == Some(byte))
+	}
+
+	/// Writes the text without joining the chunks first
+	pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+		for chunk in &self.chunks {
+			out.write_all(chunk.as_bytes())?;
+		}
+		Ok(())
+	}
+
+	pub fn replace_range(&mut self, range: Range<usize>, text: &str) {
+		assert!(self.is_char_boundary(range.start) && self.is_char_boundary(range.end));
+		let first = self.chunk_at(range.start);
+		let last = self.chunk_at(range.end);
+		let (start, last_start) = (self.starts[first], self.starts[last]);
+		let new_len = self.len - range.len() + text.len();
+		if first == last && self.chunks[first].len() + text.len() <= 2 * CHUNK_SIZE {
+			self.chunks[first].replace_range(range.start - start..range.end - start, text);
+		} else {
+			let mut joined = self.chunks[first][..range.start - start].to_owned();
+			joined += text;
+			joined += &self.chunks[last][range.end - last_start..];
+			self.chunks.splice(first..=last, split(joined));
+		}
+		if self.chunks[first].is_empty() && self.chunks.len() > 1 {
+			self.chunks.remove(first);
+		}
+		self.len = new_len;
+		self.update_starts(first);
+	}
+
+	/// Where each chunk's text is stored, to check which chunks an operation rewrote
+	#[cfg(test)]
+	pub fn chunk_addresses(&self) -> Vec<*const u8> {
+		self.chunks.iter().map(|chunk| chunk.as_ptr()).collect()
+	}
+
+	/// Recalculates where chunks start from chunk `first` on
+	fn update_starts(&mut self, first: usize) {
+		self.starts.truncate(first);
+		let mut start = self.starts.last().map_or(0, |&start| start);
+		if let Some(prev) = first.checked_sub(1) {
+			start += self.chunks[prev].len();
+		}
+		for chunk in &self.chunks[first..] {
+			self.starts.push(start);
+			start += chunk.len();
+		}
+	}
+}
+
+impl fmt::Display for Text {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.chunks.iter().try_for_each(|chunk| f.write_str(chunk))
+	}
+}
+
+/// Cuts text into chunks of about `CHUNK_SIZE`
+fn split(text: String) -> Vec<String> {
+	if text.len() <= CHUNK_SIZE {
+		return vec![text];
+	}
+	let mut chunks = Vec::with_capacity(text.len() / CHUNK_SIZE + 1);
+	let mut rest = text.as_str();
+	while !rest.is_empty() {
+		let mut end = CHUNK_SIZE.min(rest.len());
+		while !rest.is_char_boundary(end) {
+			end += 1;
+		}
+		chunks.push(rest[..end].to_owned());
+		rest = &rest[end..];
+	}
+	chunks
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::TestRng;
+
+	/// A random char boundary of `text`
+	fn boundary(rng: &mut TestRng, text: &str) -> usize {
+		let mut pos = rng.below(text.len() + 1);
+		while !text.is_char_boundary(pos) {
+			pos -= 1;
+		}
+		pos
+	}
+
+	#[test]
+	fn edits_match_a_string() {
+		let pieces = [
+			"",
+			"a",
+			"é",
+			"日本語",
+			"\n",
+			"🦀x\n",
+			"\r\n",
+			&"long é line ".repeat(3000),
+		];
+		let mut rng = TestRng::new(1);
+		let mut expected = "ab\nçd 日本\n".repeat(5000);
+		let mut text = Text::from(expected.clone());
+		for _ in 0..2000 {
+			let start = boundary(&mut rng, &expected);
+			let mut end = boundary(&mut rng, &expected[start..]) + start;
+			if rng.below(4) > 0 {
+				// mostly small edits, like typing
+				end = start + expected[start..].chars().next().map_or(0, char::len_utf8);
+			}
+			let inserted = pieces[rng.below(pieces.len())];
+			expected.replace_range(start..end, inserted);
+			text.replace_range(start..end, inserted);
+			assert_eq!(text.len(), expected.len());
+			let pos = boundary(&mut rng, &expected);
+			let end = boundary(&mut rng, &expected[pos..]) + pos;
+			assert_eq!(text.slice(pos..end), &expected[pos..end]);
+			assert_eq!(text.char_at(pos), expected[pos..].chars().next());
+			assert_eq!(text.char_before(pos), expected[..pos].chars().next_back());
+			assert_eq!(
+				text.is_char_boundary(pos + 1),
+				expected.is_char_boundary(pos + 1)
+			);
+		}
+		assert!(text.chunks.len() > 1);
+		assert!(text.bytes().eq(expected.bytes()));
+		assert_eq!(text.to_string(), expected);
+		assert!(text.lines().eq(expected.split('\n').scan(0, |start, line| {
+			let range = *start..*start + line.len();
+			*start = range.end + 1;
+			Some(range)
+		})));
+	}
+
+	#[test]
+	fn finds_matches_across_chunks() {
+		let expected = "abcé日🦀\n".repeat(CHUNK_SIZE / 4);
+		let text = Text::from(expected.clone());
+		assert!(text.chunks.len() > 2);
+		let all = 0..expected.len();
+		let snap = |mut pos: usize| {
+			while !expected.is_char_boundary(pos) {
+				pos += 1;
+			}
+			pos
+		};
+		// every piece of text around a chunk boundary, and the boundary itself, is found
+		for &boundary in &text.starts[1..] {
+			for before in 0..8 {
+				for after in 1..8 {
+					let (start, end) = (boundary - before, boundary + after);
+					if !expected.is_char_boundary(start) || !expected.is_char_boundary(end) {
+						continue;
+					}
+					let pattern = &expected[start..end];
+					assert_eq!(text.find(pattern, all.clone()), expected.find(pattern));
+					assert_eq!(text.rfind(pattern, all.clone()), expected.rfind(pattern));
+					let part = snap(boundary - 20)..snap(boundary + 20);
+					let in_part = |found: Option<usize>| found.map(|i| i + part.start);
+					assert_eq!(
+						text.find(pattern, part.clone()),
+						in_part(expected[part.clone()].find(pattern))
+					);
+					assert_eq!(
+						text.rfind(pattern, part.clone()),
+						in_part(expected[part.clone()].rfind(pattern))
+					);
+				}
+			}
+		}
+		let starts: Vec<usize> = expected.match_indices("é日🦀\na").map(|(i, _)| i).collect();
+		assert!(text.match_indices("é日🦀\na").eq(starts));
+		assert!(text.chars().eq(expected.chars()));
+	}
+
+	#[test]
+	fn typing_at_the_top_leaves_the_rest_alone() {
+		let mut text = Text::from("line of text\n".repeat(1_000_000));
+		let rest = text.chunk_addresses()[1..].to_vec();
+		for i in 0..1000 {
+			text.replace_range(i..i, "x");
+		}
+		text.replace_range(500..501, "");
+		assert_eq!(text.chunk_addresses()[1..], rest);
+		assert_eq!(text.len(), 13_000_000 + 999);
+		assert_eq!(text.slice(995..1010), "xxxxline of tex");
+	}
+}
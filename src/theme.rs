@@ -0,0 +1,363 @@
+use crossterm::{
+	queue,
+	style::{Color, ResetColor, SetBackgroundColor, SetForegroundColor},
+};
+use std::{
+	env,
+	io::{self, Write},
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+static COLORS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Foreground and background color for one kind of element; `None` keeps the terminal default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+	pub fg: Option<Color>,
+	pub bg: Option<Color>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+	pub selection: Style,
+	pub status: Style,
+	pub line_number: Style,
+	pub directory: Style,
+	pub dimmed: Style,
+	pub error: Style,
+	/// The current search match
+	pub search: Style,
+	/// The bracket at the cursor and its match
+	pub bracket: Style,
+	/// Syntax highlighting
+	pub comment: Style,
+	pub string: Style,
+	pub keyword: Style,
+}
+
+impl Style {
+	const fn new(fg: Option<Color>, bg: Option<Color>) -> Self {
+		Self { fg, bg }
+	}
+
+	pub fn queue(&self, out: &mut impl Write) -> io::Result<()> {
+		Self::reset(out)?;
+		if !COLORS_ENABLED.load(Ordering::Relaxed) {
+			return Ok(());
+		}
+		if let Some(fg) = self.fg {
+			queue!(out, SetForegroundColor(fg))?;
+		}
+		if let Some(bg) = self.bg {
+			queue!(out, SetBackgroundColor(bg))?;
+		}
+		Ok(())
+	}
+
+	pub fn reset(out: &mut impl Write) -> io::Result<()> {
+		if COLORS_ENABLED.load(Ordering::Relaxed) {
+			queue!(out, ResetColor)?;
+		}
+		Ok(())
+	}
+}
+
+/// How many colors the terminal can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSupport {
+	TrueColor,
+	Ansi256,
+	Basic,
+}
+
+impl ColorSupport {
+	/// Guesses from `$COLORTERM` and `$TERM`, assuming only the 16 basic colors when unsure
+	pub fn detect() -> Self {
+		let colorterm = env::var("COLORTERM").unwrap_or_default();
+		let term = env::var("TERM").unwrap_or_default();
+		if colorterm == "truecolor" || colorterm == "24bit" {
+			Self::TrueColor
+		} else if term.contains("256color") {
+			Self::Ansi256
+		} else {
+			Self::Basic
+		}
+	}
+
+	pub fn parse(value: &str) -> Result<Option<Self>, String> {
+		match value {
+			"auto" => Ok(None),
+			"truecolor" => Ok(Some(Self::TrueColor)),
+			"256" => Ok(Some(Self::Ansi256)),
+			"16" => Ok(Some(Self::Basic)),
+			_ => Err(format!(
+				"expected \"auto\", \"truecolor\", \"256\" or \"16\", found '{value}'"
+			)),
+		}
+	}
+
+	pub fn name(support: Option<Self>) -> &'static str {
+		match support {
+			None => "auto",
+			Some(Self::TrueColor) => "truecolor",
+			Some(Self::Ansi256) => "256",
+			Some(Self::Basic) => "16",
+		}
+	}
+
+	/// The closest color this terminal can show
+	fn limit(self, color: Color) -> Color {
+		match (self, color) {
+			(Self::TrueColor, _) => color,
+			(Self::Ansi256, Color::Rgb { r, g, b }) => Color::AnsiValue(nearest_256(r, g, b)),
+			(Self::Basic, Color::Rgb { r, g, b }) => nearest_basic(r, g, b),
+			(Self::Basic, Color::AnsiValue(value)) => {
+				let [r, g, b] = ansi_to_rgb(value);
+				nearest_basic(r, g, b)
+			}
+			_ => color,
+		}
+	}
+}
+
+/// Turns all styling into no-ops, for output that does not go to a terminal
+pub fn disable_colors() {
+	COLORS_ENABLED.store(false, Ordering::Relaxed);
+}
+
+impl Theme {
+	pub const NAMES: [&'static str; 3] = ["default", "light", "high-contrast"];
+
+	pub fn named(name: &str) -> Option<Self> {
+		let theme = match name {
+			"default" => Self::default(),
+			"light" => Self {
+				selection: Style::new(Some(Color::White), Some(Color::DarkBlue)),
+				status: Style::new(Some(Color::White), Some(Color::DarkGrey)),
+				line_number: Style::new(Some(Color::DarkGrey), None),
+				directory: Style::new(Some(Color::DarkBlue), None),
+				dimmed: Style::new(Some(Color::Grey), None),
+				error: Style::new(Some(Color::DarkRed), None),
+				search: Style::new(Some(Color::Black), Some(Color::Yellow)),
+				bracket: Style::new(Some(Color::Black), Some(Color::Cyan)),
+				comment: Style::new(Some(Color::Grey), None),
+				string: Style::new(Some(Color::DarkGreen), None),
+				keyword: Style::new(Some(Color::DarkMagenta), None),
+			},
+			"high-contrast" => Self {
+				selection: Style::new(Some(Color::Black), Some(Color::Yellow)),
+				status: Style::new(Some(Color::Black), Some(Color::White)),
+				line_number: Style::new(Some(Color::White), None),
+				directory: Style::new(Some(Color::Cyan), None),
+				dimmed: Style::new(Some(Color::Grey), None),
+				error: Style::new(Some(Color::Red), None),
+				search: Style::new(Some(Color::Black), Some(Color::Cyan)),
+				bracket: Style::new(Some(Color::Black), Some(Color::Magenta)),
+				comment: Style::new(Some(Color::Grey), None),
+				string: Style::new(Some(Color::Green), None),
+				keyword: Style::new(Some(Color::Yellow), None),
+			},
+			_ => return None,
+		};
+		Some(theme)
+	}
+
+	/// Replaces colors the terminal cannot show with the nearest ones it can
+	pub fn limit_colors(&mut self, support: ColorSupport) {
+		for style in [
+			&mut self.selection,
+			&mut self.status,
+			&mut self.line_number,
+			&mut self.directory,
+			&mut self.dimmed,
+			&mut self.error,
+			&mut self.search,
+			&mut self.bracket,
+			&mut self.comment,
+			&mut self.string,
+			&mut self.keyword,
+		] {
+			style.fg = style.fg.map(|c| support.limit(c));
+			style.bg = style.bg.map(|c| support.limit(c));
+		}
+	}
+
+	/// Overrides a single color from a config key such as `selection_bg = "#204060"`
+	pub fn set_color(&mut self, key: &str, value: &str) -> Result<(), String> {
+		let Some((element, layer)) = key.rsplit_once('_') else {
+			return Err(format!("unknown theme key '{key}'"));
+		};
+		let style = match element {
+			"selection" => &mut self.selection,
+			"status" => &mut self.status,
+			"line_number" => &mut self.line_number,
+			"directory" => &mut self.directory,
+			"dimmed" => &mut self.dimmed,
+			"error" => &mut self.error,
+			"search" => &mut self.search,
+			"bracket" => &mut self.bracket,
+			"comment" => &mut self.comment,
+			"string" => &mut self.string,
+			"keyword" => &mut self.keyword,
+			_ => return Err(format!("unknown theme element '{element}'")),
+		};
+		let color = parse_color(value)?;
+		match layer {
+			"fg" => style.fg = color,
+			"bg" => style.bg = color,
+			_ => return Err(format!("theme key '{key}' must end in _fg or _bg")),
+		}
+		Ok(())
+	}
+}
+
+impl Default for Theme {
+	fn default() -> Self {
+		Self {
+			selection: Style::new(Some(Color::Black), Some(Color::White)),
+			status: Style::default(),
+			line_number: Style::default(),
+			directory: Style::default(),
+			dimmed: Style::new(Some(Color::DarkGrey), None),
+			error: Style::new(Some(Color::Red), None),
+			search: Style::new(Some(Color::Black), Some(Color::Yellow)),
+			bracket: Style::new(Some(Color::Black), Some(Color::Cyan)),
+			comment: Style::new(Some(Color::DarkGrey), None),
+			string: Style::new(Some(Color::Green), None),
+			keyword: Style::new(Some(Color::Magenta), None),
+		}
+	}
+}
+
+/// Parses a color name like `"darkgrey"`, `"#rrggbb"`, or `"default"` for the terminal's own color
+fn parse_color(value: &str) -> Result<Option<Color>, String> {
+	let value = value.trim_matches('"');
+	if value == "default" {
+		return Ok(None);
+	}
+	if let Some(hex) = value.strip_prefix('#') {
+		let channel = |i: usize| {
+			hex.get(i..i + 2)
+				.and_then(|c| u8::from_str_radix(c, 16).ok())
+		};
+		return match (hex.len(), channel(0), channel(2), channel(4)) {
+			(6, Some(r), Some(g), Some(b)) => Ok(Some(Color::Rgb { r, g, b })),
+			_ => Err(format!("invalid color '{value}'")),
+		};
+	}
+	Color::try_from(value)
+		.map(Some)
+		.map_err(|_| format!("unknown color '{value}'"))
+}
+
+fn distance([r1, g1, b1]: [u8; 3], [r2, g2, b2]: [u8; 3]) -> i32 {
+	let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+	d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// Levels of each channel in the 6x6x6 color cube of the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Closest entry in the color cube or the gray ramp of the 256-color palette
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+	let level = |c: u8| {
+		(0..6)
+			.min_by_key(|&i| (CUBE_LEVELS[i] as i32 - c as i32).abs())
+			.unwrap()
+	};
+	let (ri, gi, bi) = (level(r), level(g), level(b));
+	let cube = 16 + 36 * ri + 6 * gi + bi;
+	let cube_rgb = [CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]];
+
+	let average = (r as usize + g as usize + b as usize) / 3;
+	let gray_index = (average.saturating_sub(8) / 10).min(23);
+	let gray = 8 + 10 * gray_index as u8;
+	if distance([gray; 3], [r, g, b]) < distance(cube_rgb, [r, g, b]) {
+		232 + gray_index as u8
+	} else {
+		cube as u8
+	}
+}
+
+/// Approximate RGB value of a 256-color palette entry
+fn ansi_to_rgb(value: u8) -> [u8; 3] {
+	match value {
+		0..=15 => BASIC[value as usize].1,
+		16..=231 => {
+			let i = (value - 16) as usize;
+			[
+				CUBE_LEVELS[i / 36],
+				CUBE_LEVELS[i / 6 % 6],
+				CUBE_LEVELS[i % 6],
+			]
+		}
+		_ => [8 + 10 * (value - 232); 3],
+	}
+}
+
+const BASIC: [(Color, [u8; 3]); 16] = [
+	(Color::Black, [0, 0, 0]),
+	(Color::DarkRed, [128, 0, 0]),
+	(Color::DarkGreen, [0, 128, 0]),
+	(Color::DarkYellow, [128, 128, 0]),
+	(Color::DarkBlue, [0, 0, 128]),
+	(Color::DarkMagenta, [128, 0, 128]),
+	(Color::DarkCyan, [0, 128, 128]),
+	(Color::Grey, [192, 192, 192]),
+	(Color::DarkGrey, [128, 128, 128]),
+	(Color::Red, [255, 0, 0]),
+	(Color::Green, [0, 255, 0]),
+	(Color::Yellow, [255, 255, 0]),
+	(Color::Blue, [0, 0, 255]),
+	(Color::Magenta, [255, 0, 255]),
+	(Color::Cyan, [0, 255, 255]),
+	(Color::White, [255, 255, 255]),
+];
+
+fn nearest_basic(r: u8, g: u8, b: u8) -> Color {
+	BASIC
+		.iter()
+		.min_by_key(|(_, rgb)| distance(*rgb, [r, g, b]))
+		.map(|(color, _)| *color)
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn nearest_256_colors() {
+		assert_eq!(nearest_256(0, 0, 0), 16);
+		assert_eq!(nearest_256(255, 255, 255), 231);
+		assert_eq!(nearest_256(255, 0, 0), 196);
+		assert_eq!(nearest_256(255, 135, 0), 208);
+		assert_eq!(nearest_256(0x20, 0x40, 0x60), 23);
+		// grays are closer to the gray ramp than to the cube
+		assert_eq!(nearest_256(128, 128, 128), 244);
+	}
+
+	#[test]
+	fn nearest_basic_colors() {
+		assert_eq!(nearest_basic(255, 0, 0), Color::Red);
+		assert_eq!(nearest_basic(128, 0, 0), Color::DarkRed);
+		assert_eq!(nearest_basic(255, 165, 0), Color::Yellow);
+		assert_eq!(nearest_basic(200, 200, 200), Color::Grey);
+		assert_eq!(nearest_basic(100, 100, 100), Color::DarkGrey);
+	}
+
+	#[test]
+	fn limit_to_support() {
+		let gray = Color::Rgb {
+			r: 128,
+			g: 128,
+			b: 128,
+		};
+		assert_eq!(ColorSupport::TrueColor.limit(gray), gray);
+		assert_eq!(ColorSupport::Ansi256.limit(gray), Color::AnsiValue(244));
+		assert_eq!(ColorSupport::Basic.limit(gray), Color::DarkGrey);
+		assert_eq!(ColorSupport::Basic.limit(Color::AnsiValue(196)), Color::Red);
+		// named colors are already among the basic ones
+		assert_eq!(ColorSupport::Ansi256.limit(Color::Cyan), Color::Cyan);
+	}
+}
@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+
+const MAX_STEPS: usize = 500;
+
+/// One change to the text: `removed` was replaced by `inserted` at byte `pos`
+#[derive(Debug, Clone)]
+pub struct Edit {
+	pub pos: usize,
+	pub removed: String,
+	pub inserted: String,
+	/// Byte position of the cursor before the change
+	pub cursor: usize,
+}
+
+impl Edit {
+	/// Typing a character right after an earlier one on the same line extends that step instead of adding one
+	fn absorb(&mut self, next: &Edit) -> bool {
+		let typed_char = next.removed.is_empty() && next.inserted.chars().count() == 1;
+		let mergeable = self.removed.is_empty()
+			&& typed_char
+			&& next.pos == self.pos + self.inserted.len()
+			&& !self.inserted.contains('\n')
+			&& !next.inserted.contains('\n');
+		if mergeable {
+			self.inserted += &next.inserted;
+		}
+		mergeable
+	}
+}
+
+/// Edits that are undone and redone together, in the order they were made
+pub type Step = Vec<Edit>;
+
+/// Undo and redo stacks of an editor, and where in them the file was last saved
+#[derive(Debug)]
+pub struct UndoHistory {
+	undo: VecDeque<Step>,
+	redo: Vec<Step>,
+	/// Collects edits between `begin_group` and `end_group`
+	group: Option<Step>,
+	/// Number of undo steps matching the file on disk, None once that state can't be reached
+	saved: Option<usize>,
+}
+
+impl Default for UndoHistory {
+	fn default() -> Self {
+		Self {
+			undo: VecDeque::new(),
+			redo: Vec::new(),
+			group: None,
+			saved: Some(0),
+		}
+	}
+}
+
+impl UndoHistory {
+	pub fn record(&mut self, edit: Edit) {
+		self.redo.clear();
+		if let Some(group) = &mut self.group {
+			group.push(edit);
+			return;
+		}
+		let at_save = self.saved == Some(self.undo.len());
+		if !at_save {
+			if let Some([last]) = self.undo.back_mut().map(Vec::as_mut_slice) {
+				if last.absorb(&edit) {
+					return;
+				}
+			}
+		}
+		self.push(vec![edit]);
+	}
+
+	/// Makes the following edits a single undo step until `end_group`
+	pub fn begin_group(&mut self) {
+		self.group = Some(Vec::new());
+	}
+
+	pub fn end_group(&mut self) {
+		match self.group.take() {
+			Some(step) if !step.is_empty() => self.push(step),
+			_ => (),
+		}
+	}
+
+	fn push(&mut self, step: Step) {
+		if self.saved.is_some_and(|saved| saved > self.undo.len()) {
+			self.saved = None;
+		}
+		self.undo.push_back(step);
+		if self.undo.len() > MAX_STEPS {
+			self.undo.pop_front();
+			self.saved = self.saved.and_then(|saved| saved.checked_sub(1));
+		}
+	}
+
+	/// Moves the latest step to the redo stack and returns it so it can be reverted
+	pub fn undo(&mut self) -> Option<&Step> {
+		let step = self.undo.pop_back()?;
+		self.redo.push(step);
+		self.redo.last()
+	}
+
+	/// Moves the latest undone step back and returns it so it can be applied again
+	pub fn redo(&mut self) -> Option<&Step> {
+		let step = self.redo.pop()?;
+		self.undo.push_back(step);
+		self.undo.back()
+	}
+
+	pub fn mark_saved(&mut self) {
+		self.saved = Some(self.undo.len());
+	}
+
+	/// Whether the text is back to what was last saved
+	pub fn is_saved(&self) -> bool {
+		self.saved == Some(self.undo.len())
+	}
+}
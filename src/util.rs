@@ -1,59 +1,586 @@
 use crossterm::{
 	cursor,
-	event::{self, Event, KeyCode},
+	event::{Event, KeyCode},
 	queue,
-	style::{Color, Colors, ResetColor, SetColors},
-	terminal,
+	style::{Attribute, SetAttribute},
+	terminal::{Clear, ClearType},
 };
-use std::io::{stdout, Write};
+use std::{
+	collections::VecDeque,
+	env, fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::config::DEFAULT_WORD_CHARS;
+use crate::keymap::{self, Command, Context};
+use crate::screen;
+use crate::status;
+use crate::theme::{Style, Theme};
+
+pub fn ask_yes_no(prompt: &str, default: bool) -> io::Result<bool> {
+	let default_index = if default { 0 } else { 1 };
+	Ok(ask_choice(prompt, &["Yes", "No"], default_index)?.map_or(default, |i| i == 0))
+}
+
+/// Asks the user to pick one of `options` on the bottom row, by its first letter or with Left/Right and Enter.
+/// Returns the index of the chosen option, or None if cancelled with Escape.
+pub fn ask_choice(prompt: &str, options: &[&str], default: usize) -> io::Result<Option<usize>> {
+	let mut selected = default;
+	loop {
+		draw_choice(prompt, options, selected, default)?;
+		let Event::Key(event) = screen::read_event()? else {
+			continue;
+		};
+		match event.code {
+			KeyCode::Left => selected = selected.checked_sub(1).unwrap_or(options.len() - 1),
+			KeyCode::Right | KeyCode::Tab => selected = (selected + 1) % options.len(),
+			KeyCode::Enter => return Ok(Some(selected)),
+			KeyCode::Esc => return Ok(None),
+			KeyCode::Char(ch) => {
+				let hotkey = |option: &&str| {
+					option
+						.chars()
+						.next()
+						.is_some_and(|c| c.eq_ignore_ascii_case(&ch))
+				};
+				if let Some(index) = options.iter().position(hotkey) {
+					return Ok(Some(index));
+				}
+			}
+			_ => (),
+		}
+	}
+}
+
+/// Draws the options with their hotkey underlined, the selected one reversed and the default one in bold
+fn draw_choice(prompt: &str, options: &[&str], selected: usize, default: usize) -> io::Result<()> {
+	let mut out = screen::out();
+	status::begin(&mut out)?;
+	queue!(out, cursor::Hide)?;
+	write!(out, "{prompt} ")?;
+	for (i, option) in options.iter().enumerate() {
+		let mut chars = option.chars();
+		let hotkey = chars.next().unwrap_or(' ');
+		write!(out, " ")?;
+		if i == selected {
+			queue!(out, SetAttribute(Attribute::Reverse))?;
+		}
+		if i == default {
+			queue!(out, SetAttribute(Attribute::Bold))?;
+		}
+		write!(out, " ")?;
+		queue!(out, SetAttribute(Attribute::Underlined))?;
+		write!(out, "{hotkey}")?;
+		queue!(out, SetAttribute(Attribute::NoUnderline))?;
+		write!(out, "{} ", chars.as_str())?;
+		queue!(out, SetAttribute(Attribute::Reset))?;
+	}
+	out.flush()
+}
+
+/// Previously entered prompt inputs, newest first
+#[derive(Debug, Default)]
+pub struct History {
+	entries: VecDeque<String>,
+}
+
+impl History {
+	const MAX_ENTRIES: usize = 100;
+
+	pub fn add(&mut self, entry: &str) {
+		if entry.is_empty() || self.entries.front().is_some_and(|e| e == entry) {
+			return;
+		}
+		self.entries.push_front(entry.to_owned());
+		self.entries.truncate(Self::MAX_ENTRIES);
+	}
 
-pub fn ask_yes_no(prompt: &str, default: bool) -> bool {
-	let options = if default { "Y/n" } else { "y/N" };
-	let prompt = format!("{prompt} [{options}]: ");
-	match read_line(&prompt).and_then(|s| s.chars().next()) {
-		Some('Y' | 'y') => true,
-		Some('N' | 'n') => false,
-		_ => default,
+	pub fn get(&self, index: usize) -> Option<&str> {
+		self.entries.get(index).map(String::as_str)
 	}
 }
 
-pub fn read_line(prompt: &str) -> Option<String> {
-	let mut response = String::new();
-	let size = terminal::size().unwrap();
-	let start_pos = cursor::MoveTo(0, size.1);
-	let width = size.0 as usize;
+/// Reads a line of input on the bottom row.
+/// If a history is given, Up and Down recall earlier entries and the accepted input is added to it.
+pub fn read_line(prompt: &str, history: Option<&mut History>) -> io::Result<Option<String>> {
+	read_input(prompt, "", false, history)
+}
+
+/// Like `read_line` but starting with `initial` already typed
+pub fn edit_line(prompt: &str, initial: &str) -> io::Result<Option<String>> {
+	read_input(prompt, initial, false, None)
+}
 
-	queue!(stdout(), start_pos).unwrap();
-	print!("{:width$}", " ");
-	queue!(stdout(), start_pos).unwrap();
-	print!("{prompt}");
-	stdout().flush().unwrap();
+/// Like `read_line` but with Tab completion of file paths, and `~` expanded in the result
+pub fn read_path(prompt: &str, history: Option<&mut History>) -> io::Result<Option<String>> {
+	let path = read_input(prompt, "", true, history)?;
+	Ok(path.map(|path| expand_home(&path).to_string_lossy().into_owned()))
+}
 
+fn read_input(
+	prompt: &str,
+	initial: &str,
+	complete_paths: bool,
+	history: Option<&mut History>,
+) -> io::Result<Option<String>> {
+	let mut input = LineInput::default();
+	input.set_text(initial.into());
+	let mut prev_was_tab = false;
+	// position in the history being shown, and what was typed before browsing it
+	let mut history_index: Option<usize> = None;
+	let mut draft = String::new();
 	loop {
-		if let Ok(Event::Key(event)) = event::read() {
-			match event.code {
-				KeyCode::Enter => break,
-				KeyCode::Char(ch) => response.push(ch),
-				KeyCode::Backspace => {
-					queue!(stdout(), start_pos).unwrap();
-					print!("{:width$}", " ");
-					response.pop();
+		input.draw(prompt)?;
+		let Event::Key(event) = screen::read_event()? else {
+			continue;
+		};
+		let command = keymap::lookup(Context::Prompt, &event);
+		if command == Some(Command::Complete) && complete_paths {
+			let candidates = input.complete_path();
+			if prev_was_tab && candidates.len() > 1 {
+				show_candidates(&candidates)?;
+			}
+			prev_was_tab = true;
+			continue;
+		}
+		prev_was_tab = false;
+		let Some(command) = command else {
+			if let KeyCode::Char(ch) = event.code {
+				input.insert(ch);
+			}
+			continue;
+		};
+		match command {
+			Command::Accept => break,
+			Command::Cancel => return Ok(None),
+			Command::Backspace => input.backspace(),
+			Command::Delete => input.delete(),
+			Command::WordLeft => input.cursor = input.prev_word(),
+			Command::WordRight => input.cursor = input.next_word(),
+			Command::Left => input.cursor = input.prev_char(),
+			Command::Right => input.cursor = input.next_char(),
+			Command::Home => input.cursor = 0,
+			Command::End => input.cursor = input.text.len(),
+			Command::Up => {
+				let Some(history) = history.as_deref() else {
+					continue;
+				};
+				let index = history_index.map_or(0, |i| i + 1);
+				if let Some(entry) = history.get(index) {
+					if history_index.is_none() {
+						draft = input.text.clone();
+					}
+					history_index = Some(index);
+					input.set_text(entry.to_owned());
+				}
+			}
+			Command::Down => match history_index {
+				Some(0) => {
+					history_index = None;
+					input.set_text(draft.clone());
+				}
+				Some(i) => {
+					history_index = Some(i - 1);
+					if let Some(entry) = history.as_deref().and_then(|h| h.get(i - 1)) {
+						input.set_text(entry.to_owned());
+					}
 				}
-				KeyCode::Esc => return None,
+				None => (),
+			},
+			_ => (),
+		}
+	}
+	let text = input.text.trim();
+	if let Some(history) = history {
+		history.add(text);
+	}
+	Ok(Some(text.into()))
+}
+
+/// Text being typed into a prompt, with a cursor that can be moved around in it
+#[derive(Default)]
+struct LineInput {
+	text: String,
+	/// Byte index into `text`, always on a grapheme boundary
+	cursor: usize,
+}
+
+impl LineInput {
+	/// Replaces the text and moves the cursor to its end
+	fn set_text(&mut self, text: String) {
+		self.cursor = text.len();
+		self.text = text;
+	}
+
+	fn insert(&mut self, ch: char) {
+		self.text.insert(self.cursor, ch);
+		self.cursor += ch.len_utf8();
+	}
+
+	fn backspace(&mut self) {
+		let start = self.prev_char();
+		self.text.replace_range(start..self.cursor, "");
+		self.cursor = start;
+	}
+
+	fn delete(&mut self) {
+		let end = self.next_char();
+		self.text.replace_range(self.cursor..end, "");
+	}
+
+	/// Start of the grapheme before the cursor
+	fn prev_char(&self) -> usize {
+		self.text[..self.cursor]
+			.grapheme_indices(true)
+			.next_back()
+			.map_or(0, |(i, _)| i)
+	}
+
+	/// End of the grapheme after the cursor
+	fn next_char(&self) -> usize {
+		self.text[self.cursor..]
+			.graphemes(true)
+			.next()
+			.map_or(self.cursor, |g| self.cursor + g.len())
+	}
+
+	fn prev_word(&self) -> usize {
+		prev_word_start(&self.text, self.cursor, DEFAULT_WORD_CHARS)
+	}
+
+	fn next_word(&self) -> usize {
+		next_word_end(&self.text, self.cursor, DEFAULT_WORD_CHARS)
+	}
+
+	/// Completes the file name before the cursor as far as it is unambiguous.
+	/// Returns all names that matched.
+	fn complete_path(&mut self) -> Vec<String> {
+		let typed = &self.text[..self.cursor];
+		let name_start = typed.rfind('/').map_or(0, |i| i + 1);
+		let (dir, partial_name) = typed.split_at(name_start);
+		let dir = match dir {
+			"" => PathBuf::from("."),
+			dir => expand_home(dir),
+		};
+		let Ok(entries) = fs::read_dir(dir) else {
+			return Vec::new();
+		};
+		let mut candidates: Vec<String> = entries
+			.flatten()
+			.filter_map(|entry| {
+				let name = entry.file_name().to_string_lossy().into_owned();
+				let hidden = name.starts_with('.') && !partial_name.starts_with('.');
+				if !name.starts_with(partial_name) || hidden {
+					return None;
+				}
+				let is_dir = entry.path().is_dir();
+				Some(if is_dir { name + "/" } else { name })
+			})
+			.collect();
+		candidates.sort_unstable();
+
+		let Some(first) = candidates.first() else {
+			return candidates;
+		};
+		let common_len = candidates.iter().fold(first.len(), |len, name| {
+			first[..len]
+				.char_indices()
+				.zip(name.chars())
+				.find(|((_, a), b)| a != b)
+				.map_or(len.min(name.len()), |((i, _), _)| i)
+		});
+		let completion = first[partial_name.len()..common_len].to_owned();
+		self.text.insert_str(self.cursor, &completion);
+		self.cursor += completion.len();
+		candidates
+	}
+
+	/// Draws the prompt on the bottom row, scrolling the input horizontally to keep the cursor visible
+	fn draw(&self, prompt: &str) -> io::Result<()> {
+		let width = screen::size().0;
+		let prompt_width = prompt.width();
+		let available = (width as usize).saturating_sub(prompt_width + 1).max(1);
+		let cursor = self.text[..self.cursor].width();
+		// leading columns hidden so that the cursor fits, rounded up to a whole grapheme
+		let mut skip = 0;
+		let mut visible = String::new();
+		let mut visible_width = 0;
+		for grapheme in self.text.graphemes(true) {
+			let w = grapheme.width();
+			if cursor - skip >= available {
+				skip += w;
+			} else if visible_width + w <= available {
+				visible += grapheme;
+				visible_width += w;
+			} else {
+				break;
+			}
+		}
+
+		let mut out = screen::out();
+		status::begin(&mut out)?;
+		write!(out, "{prompt}{visible}")?;
+		let column = prompt_width + cursor - skip;
+		queue!(
+			out,
+			screen::move_to(column as u16, status::row()),
+			cursor::Show
+		)?;
+		out.flush()
+	}
+}
+
+/// Lists completion candidates on the row above the prompt
+fn show_candidates(candidates: &[String]) -> io::Result<()> {
+	screen::overlay_drawn();
+	let width = screen::size().0;
+	let text = truncate_end(&candidates.join("  "), width as usize);
+	queue!(
+		screen::out(),
+		screen::move_to(0, status::row().saturating_sub(1)),
+		Clear(ClearType::CurrentLine)
+	)?;
+	write!(screen::out(), "{text}")
+}
+
+/// Whether `c` belongs to a word: any letter or digit, or one of the `extra` characters
+pub fn is_word_char(c: char, extra: &str) -> bool {
+	c.is_alphanumeric() || extra.contains(c)
+}
+
+/// Start of the word before byte `pos`, skipping anything in between
+pub fn prev_word_start(text: &str, pos: usize, extra: &str) -> usize {
+	let before = &text[..pos];
+	let end = before.trim_end_matches(|c| !is_word_char(c, extra)).len();
+	before[..end]
+		.rfind(|c| !is_word_char(c, extra))
+		.map_or(0, |i| i + before[i..].chars().next().unwrap().len_utf8())
+}
+
+/// End of the word after byte `pos`, skipping anything in between
+pub fn next_word_end(text: &str, pos: usize, extra: &str) -> usize {
+	let after = &text[pos..];
+	let start = after.len() - after.trim_start_matches(|c| !is_word_char(c, extra)).len();
+	after[start..]
+		.find(|c| !is_word_char(c, extra))
+		.map_or(text.len(), |i| pos + start + i)
+}
+
+/// Line and optionally column to open a file at, both counting from 1
+pub type Position = (usize, Option<usize>);
+
+/// Splits a `:line` or `:line:column` suffix, as printed by compilers, off a path that doesn't
+/// exist as given but does without the suffix
+pub fn split_position(path: &Path) -> (PathBuf, Option<Position>) {
+	if path.exists() {
+		return (path.into(), None);
+	}
+	let text = path.to_string_lossy();
+	let parts: Vec<&str> = text.rsplitn(3, ':').collect();
+	let number = |part: &str| part.parse::<usize>().ok();
+	let candidates = match parts[..] {
+		[column, line, file] => [
+			number(line)
+				.zip(number(column))
+				.map(|(l, c)| (file, (l, Some(c)))),
+			number(column).map(|l| (&text[..text.len() - column.len() - 1], (l, None))),
+		],
+		[line, file] => [number(line).map(|l| (file, (l, None))), None],
+		_ => [None, None],
+	};
+	for (file, position) in candidates.into_iter().flatten() {
+		if Path::new(file).exists() {
+			return (file.into(), Some(position));
+		}
+	}
+	(path.into(), None)
+}
+
+/// Replaces a leading `~` with the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+	let home = env::var_os("HOME").map(PathBuf::from);
+	match (path.strip_prefix('~'), home) {
+		(Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+			home.join(rest.trim_start_matches('/'))
+		}
+		_ => PathBuf::from(path),
+	}
+}
+
+/// `path` made absolute with symlinks resolved, the form the navigator compares paths in.
+/// A file that doesn't exist yet is resolved through its directory.
+pub fn canonical_path(path: &Path) -> PathBuf {
+	if let Ok(path) = path.canonicalize() {
+		return path;
+	}
+	let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+		return path.to_owned();
+	};
+	let dir = if dir.as_os_str().is_empty() {
+		Path::new(".")
+	} else {
+		dir
+	};
+	dir.canonicalize()
+		.map_or_else(|_| path.to_owned(), |dir| dir.join(name))
+}
+
+/// Lets the user choose an item from a list drawn above the bottom row.
+/// Returns the index of the chosen item, or None if cancelled with Escape.
+pub fn pick(title: &str, items: &[String], theme: &Theme) -> io::Result<Option<usize>> {
+	if items.is_empty() {
+		return Ok(None);
+	}
+	screen::overlay_drawn();
+	let mut out = screen::out();
+	let mut selected: usize = 0;
+	let mut scroll = 0;
+	loop {
+		let width = screen::size().0 as usize;
+		let bottom = status::row() as usize;
+		// a terminal one row tall still gets a row, shared with the hint
+		let rows = items.len().min(bottom).max(1);
+		let top = bottom.saturating_sub(rows);
+		scroll = scroll.clamp((selected + 1).saturating_sub(rows), selected);
+
+		for (row, index) in (scroll..scroll + rows).enumerate() {
+			queue!(out, screen::move_to(0, (top + row) as u16))?;
+			if index == selected {
+				theme.selection.queue(&mut out)?;
+			} else {
+				theme.status.queue(&mut out)?;
+			}
+			write!(out, "{:width$}", truncate_end(&items[index], width))?;
+			Style::reset(&mut out)?;
+		}
+		status::begin(&mut out)?;
+		let hint = format!("{title} (Enter: choose, Esc: cancel)");
+		write!(out, "{:width$}", truncate_end(&hint, width))?;
+		out.flush()?;
+
+		if let Event::Key(event) = screen::read_event()? {
+			match event.code {
+				KeyCode::Up => selected = selected.checked_sub(1).unwrap_or(items.len() - 1),
+				KeyCode::Down => selected = (selected + 1) % items.len(),
+				KeyCode::Enter => return Ok(Some(selected)),
+				KeyCode::Esc => return Ok(None),
 				_ => (),
 			}
 		}
-		queue!(stdout(), start_pos).unwrap();
-		print!("{prompt}{response}");
-		stdout().flush().unwrap();
 	}
-	Some(response.trim().into())
 }
 
-pub fn color_highlight() {
-	queue!(stdout(), SetColors(Colors::new(Color::Black, Color::White))).unwrap();
+/// File size for display, like `512 B` or `1.5 MB`
+pub fn human_size(bytes: u64) -> String {
+	const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+	if bytes < 1024 {
+		return format!("{bytes} B");
+	}
+	let mut size = bytes as f64 / 1024.0;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+	format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Formats a time as `YYYY-MM-DD HH:MM` in UTC
+pub fn format_time(time: SystemTime) -> String {
+	let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+	let (days, secs) = (secs / 86400, secs % 86400);
+	// civil date from days since the epoch, from Howard Hinnant's date algorithms
+	let z = days + 719468;
+	let era = z / 146097;
+	let day_of_era = z % 146097;
+	let year_of_era =
+		(day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let mp = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = year_of_era + era * 400 + u64::from(month <= 2);
+	format!(
+		"{year:04}-{month:02}-{day:02} {:02}:{:02}",
+		secs / 3600,
+		secs % 3600 / 60
+	)
 }
 
-pub fn color_reset() {
-	queue!(stdout(), ResetColor).unwrap();
+/// Shortens text to fit within `width` columns by cutting off the start
+pub fn truncate_start(text: &str, width: usize) -> String {
+	if text.width() <= width {
+		return text.into();
+	}
+	let mut kept = Vec::new();
+	let mut kept_width = 1;
+	for ch in text.chars().rev() {
+		kept_width += ch.width().unwrap_or(0);
+		if kept_width > width {
+			break;
+		}
+		kept.push(ch);
+	}
+	format!("…{}", kept.iter().rev().collect::<String>())
+}
+
+/// Shortens text to fit within `width` columns by cutting off the end
+pub fn truncate_end(text: &str, width: usize) -> String {
+	if text.width() <= width {
+		return text.into();
+	}
+	let mut kept = String::new();
+	let mut kept_width = 1;
+	for ch in text.chars() {
+		kept_width += ch.width().unwrap_or(0);
+		if kept_width > width {
+			break;
+		}
+		kept.push(ch);
+	}
+	format!("{kept}…")
+}
+
+/// Deterministic pseudo-random numbers for tests that try many edits
+#[cfg(test)]
+pub struct TestRng(u64);
+
+#[cfg(test)]
+impl TestRng {
+	pub fn new(seed: u64) -> Self {
+		Self(seed | 1)
+	}
+
+	/// A number below `max`
+	pub fn below(&mut self, max: usize) -> usize {
+		// xorshift64
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		(self.0 % max as u64) as usize
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crossterm::event::{KeyEvent, KeyModifiers};
+	use std::sync::PoisonError;
+
+	#[test]
+	fn pick_fits_a_terminal_one_row_tall() {
+		let _lock = screen::TEST_LOCK
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner);
+		let events = [KeyCode::Down, KeyCode::Enter]
+			.map(|code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+		screen::set_backend(Box::new(screen::Scripted::new(events, (40, 1))));
+		let items = ["one".to_owned(), "two".to_owned()];
+		assert_eq!(pick("Pick", &items, &Theme::default()).unwrap(), Some(1));
+	}
 }